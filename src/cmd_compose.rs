@@ -0,0 +1,100 @@
+use anyhow::{Context, Result, bail};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use wasm_compose::graph::{Component, CompositionGraph, EncodeOptions};
+use wasmparser::{Validator, WasmFeatures};
+
+/// Composes the component in `socket` with the components in `plugs`, in the
+/// manner of `wac plug`, writing the result to `output`.
+///
+/// Each import of `socket` which has the same name as an export of a plug is
+/// satisfied by that export.  The remaining imports of `socket`, and the
+/// imports of the plugs, become imports of the composed component, which has
+/// the same exports as `socket`.
+///
+/// Returns the names of the imports satisfied by each plug.
+pub fn compose(
+    socket: &Path,
+    plugs: &[PathBuf],
+    output: &Path,
+) -> Result<BTreeMap<String, String>> {
+    let plugs = plugs
+        .iter()
+        .map(|plug| {
+            let bytes =
+                fs::read(plug).with_context(|| format!("failed to read `{}`", plug.display()))?;
+            Ok((format!("`{}`", plug.display()), bytes))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    compose_plugs(socket, &plugs, output)
+}
+
+/// Like [`compose`], but with each plug given as a description, used in
+/// messages, and the bytes of the component.
+pub fn compose_plugs(
+    socket: &Path,
+    plugs: &[(String, Vec<u8>)],
+    output: &Path,
+) -> Result<BTreeMap<String, String>> {
+    let mut validator = Validator::new_with_features(WasmFeatures::all());
+    let mut graph = CompositionGraph::new();
+
+    let component = Component::from_file(&mut validator, "socket", socket)?;
+    let imports = component
+        .imports()
+        .map(|(index, name, _)| (name.to_string(), index))
+        .collect::<Vec<_>>();
+    let socket_id = graph.add_component(component)?;
+    let socket_instance = graph.instantiate(socket_id)?;
+
+    let mut plugged = BTreeMap::<String, String>::new();
+    for (index, (plug, bytes)) in plugs.iter().enumerate() {
+        let component =
+            Component::from_bytes(&mut validator, format!("plug{index}"), bytes.as_slice())
+                .with_context(|| format!("failed to parse {plug}"))?;
+        let matches = imports
+            .iter()
+            .filter_map(|(name, import)| {
+                let (export, _, _) = component.export_by_name(name)?;
+                Some((name, *import, export))
+            })
+            .collect::<Vec<_>>();
+        if matches.is_empty() {
+            bail!(
+                "{plug} does not export anything imported by `{}`",
+                socket.display()
+            );
+        }
+
+        let plug_id = graph.add_component(component)?;
+        let plug_instance = graph.instantiate(plug_id)?;
+        for (name, import, export) in matches {
+            if let Some(other) = plugged.get(name) {
+                bail!("both {other} and {plug} export `{name}`");
+            }
+            graph
+                .connect(plug_instance, Some(export), socket_instance, import)
+                .with_context(|| {
+                    format!("failed to satisfy import `{name}` with the export of {plug}")
+                })?;
+            plugged.insert(name.clone(), plug.clone());
+        }
+    }
+
+    let bytes = graph
+        .encode(EncodeOptions {
+            define_components: true,
+            export: Some(socket_instance),
+            validate: false,
+        })
+        .context("failed to encode composed component")?;
+    Validator::new_with_features(WasmFeatures::all())
+        .validate_all(&bytes)
+        .context("composed component is invalid")?;
+    fs::write(output, bytes).with_context(|| format!("failed to write `{}`", output.display()))?;
+
+    Ok(plugged)
+}
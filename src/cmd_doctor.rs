@@ -0,0 +1,449 @@
+use crate::{
+    command::WitOpts,
+    config::{self, CONFIG_FILE_NAME, Origin, WitConfig},
+    toolchain::{self, Toolchain},
+    utils::{
+        WASIP1_SNAPSHOT_ADAPT, adapter_interfaces, check_go_async_support, check_go_version,
+        discovery_go, go_version, parse_wit_from_config, world_name, world_needs_async,
+    },
+};
+use anyhow::{Result, bail};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use wasmparser::{KnownCustom, Parser, Payload};
+
+/// The oldest Wasmtime release which the components built by componentize-go
+/// are tested with.
+const MIN_WASMTIME_MAJOR: u32 = 44;
+
+#[derive(Clone, Copy)]
+enum Status {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// Collects and prints the results of each check.
+#[derive(Default)]
+struct Report {
+    errors: usize,
+    warnings: usize,
+}
+
+impl Report {
+    fn section(&self, title: &str) {
+        println!("\n{title}");
+    }
+
+    fn item(&mut self, status: Status, message: impl AsRef<str>) {
+        let tag = match status {
+            Status::Ok => "ok",
+            Status::Warning => {
+                self.warnings += 1;
+                "warning"
+            }
+            Status::Error => {
+                self.errors += 1;
+                "error"
+            }
+        };
+        println!("  [{tag}] {}", message.as_ref());
+    }
+
+    fn info(&self, message: impl AsRef<str>) {
+        println!("        {}", message.as_ref());
+    }
+}
+
+/// Check the environment componentize-go runs in, printing a report of any
+/// problems found, and fail if there are errors.
+pub fn run_doctor(wit_opts: &WitOpts, go_path: Option<&Path>) -> Result<()> {
+    let mut report = Report::default();
+
+    let go = check_go(&mut report, go_path);
+    check_toolchains(&mut report);
+    let config = config::discover(wit_opts, go_path);
+    check_config(&mut report, wit_opts, go_path, &config);
+    check_world(&mut report, wit_opts, config, go.as_deref());
+    check_adapter(&mut report);
+    check_wasmtime(&mut report);
+
+    println!(
+        "\n{} error(s), {} warning(s)",
+        report.errors, report.warnings
+    );
+    if report.errors > 0 {
+        bail!("componentize-go doctor found {} error(s)", report.errors);
+    }
+    Ok(())
+}
+
+/// Check the Go in PATH (or the one specified with `--go`), returning it if
+/// found.
+fn check_go(report: &mut Report, go_path: Option<&Path>) -> Option<PathBuf> {
+    report.section("Go");
+
+    let go = match go_path {
+        Some(go) => go.to_path_buf(),
+        None => match which::which("go") {
+            Ok(go) => go,
+            Err(_) => {
+                report.item(
+                    Status::Warning,
+                    format!("`go` not found in PATH; {}", fallback()),
+                );
+                return None;
+            }
+        },
+    };
+
+    match go_version(&go) {
+        Ok(version) => report.item(Status::Ok, format!("{} runs {version}", go.display())),
+        Err(e) => {
+            report.item(Status::Error, format!("{}: {e:#}", go.display()));
+            return None;
+        }
+    }
+
+    match check_go_version(&go) {
+        Ok(()) => report.item(Status::Ok, "version is compatible"),
+        Err(e) => report.item(Status::Warning, format!("{e:#}; {}", fallback())),
+    }
+
+    match check_go_async_support(&go) {
+        Ok(()) => report.item(Status::Ok, "supports async"),
+        Err(e) => report.item(
+            Status::Warning,
+            format!(
+                "does not support async ({e:#}); for worlds which need it, {}",
+                fallback()
+            ),
+        ),
+    }
+
+    Some(go)
+}
+
+/// Check the patched Go toolchains in the cache directory.
+fn check_toolchains(report: &mut Report) {
+    report.section("Patched Go toolchains");
+
+    let root = match toolchain::root() {
+        Ok(root) => root,
+        Err(e) => {
+            report.item(Status::Error, format!("{e:#}"));
+            return;
+        }
+    };
+    report.item(Status::Ok, format!("cache directory: {}", root.display()));
+
+    // Lock files remain after an installation, so only those of toolchains
+    // which are not installed are of note.  They are not locked here, which
+    // would hold up an installation in progress.
+    let mut pending = false;
+    for path in toolchain::lock_files().unwrap_or_default() {
+        let Some(version) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        if Toolchain::new(version).is_ok_and(|toolchain| toolchain.is_installed()) {
+            continue;
+        }
+        pending = true;
+        report.item(
+            Status::Warning,
+            format!(
+                "`{version}` is not installed, but has a lock file ({}); another \
+                 componentize-go may be installing it, or an installation was interrupted",
+                path.display()
+            ),
+        );
+    }
+    if !pending {
+        report.item(Status::Ok, "no installation is in progress or interrupted");
+    }
+
+    let selected = match Toolchain::selected() {
+        Ok(selected) => selected,
+        Err(e) => {
+            report.item(Status::Error, format!("{e:#}"));
+            return;
+        }
+    };
+    if selected.is_installed() {
+        report.item(
+            Status::Ok,
+            format!("selected toolchain `{}` is installed", selected.version),
+        );
+    } else {
+        match selected.download_blocker() {
+            None => report.item(
+                Status::Ok,
+                format!(
+                    "selected toolchain `{}` is not installed; it will be downloaded if needed",
+                    selected.version
+                ),
+            ),
+            Some(why) => report.item(
+                Status::Warning,
+                format!(
+                    "selected toolchain `{}` is not installed, and cannot be downloaded: {why}",
+                    selected.version
+                ),
+            ),
+        }
+    }
+
+    for toolchain in toolchain::installed().unwrap_or_default() {
+        if !toolchain.is_installed() {
+            report.item(
+                Status::Warning,
+                format!(
+                    "`{}` is incomplete; run `componentize-go toolchain prune`",
+                    toolchain.version
+                ),
+            );
+        } else if toolchain.version != selected.version {
+            report.info(format!("also installed: `{}`", toolchain.version));
+        }
+    }
+}
+
+/// Report the WIT paths and worlds found in `componentize-go.toml` files, as
+/// discovered in `config`.
+fn check_config(
+    report: &mut Report,
+    wit_opts: &WitOpts,
+    go_path: Option<&Path>,
+    config: &Result<WitConfig>,
+) {
+    report.section(&format!("{CONFIG_FILE_NAME} files"));
+
+    if wit_opts.ignore_toml_files {
+        report.item(Status::Ok, "skipped due to `--ignore-toml-files`");
+        return;
+    }
+
+    match discovery_go(go_path) {
+        Ok(None) => report.item(
+            Status::Warning,
+            "no Go available to search dependencies with",
+        ),
+        Err(e) => report.item(Status::Warning, format!("{e:#}")),
+        _ => {}
+    }
+
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            report.item(Status::Error, format!("{e:#}"));
+            return;
+        }
+    };
+
+    let modules = config
+        .sources
+        .iter()
+        .filter_map(|source| match &source.origin {
+            Origin::Module(module) => Some(module.as_str()),
+            Origin::CommandLine => None,
+        })
+        .collect::<BTreeSet<_>>();
+    if modules.is_empty() {
+        report.item(Status::Ok, "none found");
+    }
+    for module in modules {
+        report.item(Status::Ok, format!("Go module `{module}`"));
+        for source in config
+            .sources
+            .iter()
+            .filter(|source| source.origin == Origin::Module(module.into()))
+        {
+            if source.path.exists() {
+                report.info(format!("WIT path {}", source.path.display()));
+            } else {
+                report.item(
+                    Status::Error,
+                    format!("WIT path {} does not exist", source.path.display()),
+                );
+            }
+        }
+    }
+    if !config.worlds.is_empty() {
+        report.info(format!("worlds: {}", config.worlds.join(", ")));
+    }
+}
+
+/// Report the target world resolved using `config` and whether it needs
+/// async support.
+fn check_world(
+    report: &mut Report,
+    wit_opts: &WitOpts,
+    config: Result<WitConfig>,
+    go: Option<&Path>,
+) {
+    report.section("Target world");
+
+    let Ok(config) = config else {
+        report.info("skipped, since the configuration could not be found");
+        return;
+    };
+    let (resolve, world, _) = match parse_wit_from_config(wit_opts, config) {
+        Ok(result) => result,
+        Err(e) => {
+            report.item(Status::Error, format!("{e:#}"));
+            return;
+        }
+    };
+
+    let item = &resolve.worlds[world];
+    let name = world_name(&resolve, world);
+    report.item(
+        Status::Ok,
+        format!(
+            "`{name}` ({} import(s), {} export(s))",
+            item.imports.len(),
+            item.exports.len()
+        ),
+    );
+
+    let uses = world_needs_async(&resolve, world);
+    if uses.is_empty() {
+        report.item(Status::Ok, "does not need async support");
+        return;
+    }
+    let async_ok = go.is_some_and(|go| check_go_async_support(go).is_ok());
+    report.item(
+        if async_ok {
+            Status::Ok
+        } else {
+            Status::Warning
+        },
+        if async_ok {
+            "needs async support, which the Go above provides".to_string()
+        } else {
+            format!("needs async support; {}", fallback())
+        },
+    );
+    for item in &uses {
+        report.info(item.to_string());
+    }
+}
+
+/// What happens when building needs a patched Go, which the Go checked above
+/// does not provide.
+fn fallback() -> String {
+    match Toolchain::selected() {
+        Ok(selected) if selected.is_installed() => {
+            format!(
+                "the installed patched Go `{}` will be used",
+                selected.version
+            )
+        }
+        Ok(selected) => match selected.download_blocker() {
+            None => format!(
+                "the patched Go `{}` will be downloaded when building",
+                selected.version
+            ),
+            Some(why) => format!(
+                "the patched Go `{}` cannot be downloaded ({why}); use `--go` to specify one, \
+                 or build one with `componentize-go toolchain install --source <DIR>`",
+                selected.version
+            ),
+        },
+        Err(e) => format!("no patched Go can be selected: {e:#}"),
+    }
+}
+
+/// Report the WASI version targeted by the embedded adapter and the tools
+/// which produced it.
+fn check_adapter(report: &mut Report) {
+    report.section("Embedded adapter");
+
+    let interfaces = match adapter_interfaces(WASIP1_SNAPSHOT_ADAPT) {
+        Ok(interfaces) => interfaces,
+        Err(e) => {
+            report.item(Status::Error, format!("failed to parse adapter: {e:#}"));
+            return;
+        }
+    };
+    let versions = interfaces
+        .versions()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    report.item(
+        Status::Ok,
+        format!(
+            "wasi_snapshot_preview1.reactor.wasm imports WASI {}",
+            versions.join(", ")
+        ),
+    );
+
+    let mut producers = Vec::new();
+    for payload in Parser::new(0).parse_all(WASIP1_SNAPSHOT_ADAPT).flatten() {
+        if let Payload::CustomSection(reader) = payload
+            && let KnownCustom::Producers(reader) = reader.as_known()
+        {
+            for field in reader.into_iter().flatten() {
+                for value in field.values.into_iter().flatten() {
+                    producers.push(format!("{} {}", value.name, value.version));
+                }
+            }
+        }
+    }
+    if !producers.is_empty() {
+        report.info(format!("produced by {}", producers.join(", ")));
+    }
+}
+
+/// Check for a `wasmtime` in PATH which can run the components built.
+fn check_wasmtime(report: &mut Report) {
+    report.section("Wasmtime");
+
+    let Ok(wasmtime) = which::which("wasmtime") else {
+        report.item(
+            Status::Warning,
+            format!(
+                "`wasmtime` not found in PATH; install Wasmtime {MIN_WASMTIME_MAJOR} or later \
+                 to run the components built"
+            ),
+        );
+        return;
+    };
+
+    let version = Command::new(&wasmtime)
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+    let Some(version) = version else {
+        report.item(
+            Status::Warning,
+            format!("failed to run `{} --version`", wasmtime.display()),
+        );
+        return;
+    };
+
+    let major = version
+        .split_whitespace()
+        .nth(1)
+        .and_then(|v| v.split('.').next())
+        .and_then(|v| v.parse::<u32>().ok());
+    match major {
+        Some(major) if major >= MIN_WASMTIME_MAJOR => {
+            report.item(Status::Ok, format!("{} ({version})", wasmtime.display()))
+        }
+        _ => report.item(
+            Status::Warning,
+            format!(
+                "{} ({version}) may be too old; Wasmtime {MIN_WASMTIME_MAJOR} or later is \
+                 recommended",
+                wasmtime.display()
+            ),
+        ),
+    }
+}
@@ -0,0 +1,111 @@
+use crate::toolchain::{self, InstallOptions, KNOWN_VERSIONS, Toolchain};
+use anyhow::{Result, bail};
+use std::collections::BTreeSet;
+
+/// Print each installed toolchain and each known release, marking the one
+/// selected for the current project.
+pub fn list_toolchains() -> Result<()> {
+    let selected = Toolchain::selected()?;
+    let installed = toolchain::installed()?;
+
+    let versions = installed
+        .iter()
+        .map(|toolchain| toolchain.version.as_str())
+        .chain(KNOWN_VERSIONS.iter().copied())
+        .chain([selected.version.as_str()])
+        .collect::<BTreeSet<_>>();
+
+    for version in versions {
+        let toolchain = Toolchain::new(version)?;
+        let mut notes = Vec::new();
+        if version == selected.version {
+            notes.push("selected");
+        }
+        if toolchain.is_installed() {
+            notes.push("installed");
+        } else if toolchain.dir.exists() {
+            notes.push("incomplete");
+        }
+        if !KNOWN_VERSIONS.contains(&version) {
+            notes.push("unknown");
+        }
+
+        if notes.is_empty() {
+            println!("{version}");
+        } else {
+            println!("{version} ({})", notes.join(", "));
+        }
+    }
+
+    Ok(())
+}
+
+/// Install the specified toolchain, or the one selected for the current
+/// project if `None`, as described by `options`.
+pub fn install_toolchain(version: Option<&str>, options: InstallOptions) -> Result<()> {
+    let toolchain = toolchain_or_selected(version)?;
+    if !KNOWN_VERSIONS.contains(&toolchain.version.as_str()) {
+        eprintln!(
+            "Note: `{}` is not a release known to work with this version of componentize-go.",
+            toolchain.version
+        );
+    }
+    if toolchain.is_installed() {
+        eprintln!("{} is already installed.", toolchain.version);
+    } else {
+        toolchain.install(&options)?;
+    }
+    println!("{}", toolchain.bin().display());
+    Ok(())
+}
+
+/// Print the path to the `go` binary of the specified toolchain, or the one
+/// selected for the current project if `None`.
+pub fn toolchain_path(version: Option<&str>) -> Result<()> {
+    let toolchain = toolchain_or_selected(version)?;
+    if !toolchain.is_installed() {
+        bail!(
+            "toolchain `{0}` is not installed; run `componentize-go toolchain install {0}`",
+            toolchain.version
+        );
+    }
+    println!("{}", toolchain.bin().display());
+    Ok(())
+}
+
+pub fn remove_toolchain(version: &str) -> Result<()> {
+    let toolchain = Toolchain::new(version)?;
+    if !toolchain.dir.exists() {
+        bail!("toolchain `{version}` is not installed");
+    }
+    toolchain.remove(toolchain::lock_timeout()?)?;
+    eprintln!("Removed {}.", toolchain.dir.display());
+    Ok(())
+}
+
+/// Remove every installed toolchain except the one selected for the current
+/// project (unless `all` is true), plus any incomplete installations or
+/// extractions and directories left behind by earlier versions of
+/// componentize-go, after moving any release they installed into place.
+pub fn prune_toolchains(all: bool) -> Result<()> {
+    let selected = Toolchain::selected()?;
+    let timeout = toolchain::lock_timeout()?;
+    toolchain::migrate_legacy(timeout)?;
+    for toolchain in toolchain::installed()? {
+        if all || toolchain.version != selected.version || !toolchain.is_installed() {
+            toolchain.remove(timeout)?;
+            eprintln!("Removed {}.", toolchain.dir.display());
+        }
+    }
+    for dir in toolchain::remove_stale()? {
+        eprintln!("Removed {}.", dir.display());
+    }
+    Ok(())
+}
+
+fn toolchain_or_selected(version: Option<&str>) -> Result<Toolchain> {
+    match version {
+        Some(version) => Toolchain::new(version),
+        None => Toolchain::selected(),
+    }
+}
@@ -1,12 +1,28 @@
 use crate::{
     cmd_bindings::generate_bindings,
     cmd_build::build_module,
+    cmd_compose::compose,
+    cmd_doctor::run_doctor,
     cmd_test::build_test_module,
-    utils::{dummy_wit, embed_wit, module_to_component, parse_wit, pick_go},
+    cmd_toolchain::{
+        install_toolchain, list_toolchains, prune_toolchains, remove_toolchain, toolchain_path,
+    },
+    config::{self, ImportRule, Origin, WitConfig},
+    features::{parse_features, validate_features},
+    preflight::check_module_exports,
+    stubs::stub_unused_imports,
+    targets::{check_host_profile, check_import_policy, check_target_world},
+    toolchain::InstallOptions,
+    utils::{
+        dummy_wit, embed_wit, go_env_vars, module_to_component, parse_wit, parse_wit_with_config,
+        pick_go, select_adapter,
+    },
+    virt::{Virtualization, virtualize},
 };
-use anyhow::{Result, anyhow};
-use clap::{Parser, Subcommand};
-use std::{ffi::OsString, path::PathBuf};
+use anyhow::{Context, Result, anyhow};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::{collections::BTreeMap, ffi::OsString, path::PathBuf, process, time::Duration};
+use wit_parser::{Resolve, WorldId};
 
 /// A tool that creates Go WebAssembly components.
 #[derive(Parser)]
@@ -46,15 +62,65 @@ pub struct WitOpts {
     /// option is specified, `componentize-go` will use `go list` to scan the
     /// current Go module and its dependencies to find any
     /// `componentize-go.toml` files, and the WIT worlds referenced by any such
-    /// files will be used.
+    /// files will be used.  Use `--add-world` to target a world in addition to
+    /// those.
     #[arg(long, short = 'w')]
     pub world: Vec<String>,
 
+    /// Name of a world to target in addition to any referenced by
+    /// `componentize-go.toml` files.
+    ///
+    /// Unlike `--world`, this does not cause the worlds referenced by
+    /// `componentize-go.toml` files to be ignored.  This may be specified more
+    /// than once.
+    #[arg(long)]
+    pub add_world: Vec<String>,
+
+    /// Module path of a Go dependency whose `componentize-go.toml` file should
+    /// be ignored, e.g. `github.com/example/sdk`.
+    ///
+    /// Neither the worlds nor the WIT paths referenced by that file will be
+    /// used.  This may be specified more than once.
+    #[arg(long)]
+    pub exclude_module: Vec<String>,
+
+    /// Use the specified WIT path to supply a package which is defined in more
+    /// than one place, e.g. `--pin-package wasi:io@0.2.0=./wit/deps/io`.
+    ///
+    /// The version may be omitted to pin every version of the package found in
+    /// that path.  A pinned version is used in place of any other
+    /// semver-compatible version, as with `--package-version`.  This may be
+    /// specified more than once.
+    #[arg(long, value_name = "PACKAGE=PATH", value_parser = parse_package_pin)]
+    pub pin_package: Vec<(String, PathBuf)>,
+
+    /// Use the specified version of a WIT package, e.g. `wasi:io@0.2.0`.
+    ///
+    /// By default, when more than one semver-compatible version of a package is
+    /// found, the highest one is used and references to the others are
+    /// redirected to it.  This may be specified more than once.
+    #[arg(long, value_name = "PACKAGE@VERSION")]
+    pub package_version: Vec<String>,
+
     /// If `true`, skip scanning the current Go module's dependencies for
     /// `componentize-go.toml` files.
+    ///
+    /// The `[policy]` section of the current module's file is applied
+    /// regardless.
+    ///
+    /// The results of the scan are cached in the current user's cache
+    /// directory, and are refreshed whenever the Go toolchain, `go.mod`,
+    /// `go.sum`, `go.work`, `GOFLAGS`, `GOOS`, `GOARCH`, `GOTOOLCHAIN`, a
+    /// discovered `componentize-go.toml` file, or a WIT file it references
+    /// changes.
     #[arg(long)]
     pub ignore_toml_files: bool,
 
+    /// How to find the current Go module's dependencies when scanning them for
+    /// `componentize-go.toml` files.
+    #[arg(long, value_enum, default_value_t = Discovery::GoList)]
+    pub discovery: Discovery,
+
     /// Whether or not to activate all WIT features when processing WIT files.
     ///
     /// This enables using `@unstable` annotations in WIT files.
@@ -69,6 +135,105 @@ pub struct WitOpts {
     pub features: Vec<String>,
 }
 
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Discovery {
+    /// Run `go list -m all`, which may download missing modules.
+    GoList,
+
+    /// Read `go.mod`, `go.work` and their `replace` directives directly and
+    /// look up dependencies in the module cache (`GOMODCACHE`) without
+    /// running `go`.
+    ///
+    /// Modules which have not been downloaded are reported as errors, as are
+    /// `go.mod` files declaring a `go` version below 1.17, which may not list
+    /// indirect dependencies.  The results of this mode are not cached.
+    GoMod,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum AsyncMode {
+    /// Require async support only if the target world uses it.
+    Auto,
+
+    /// Always require async support.
+    Require,
+
+    /// Fail if the target world uses async.
+    Forbid,
+}
+
+/// The kind of WASI Preview 1 adapter used to turn a module into a component.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Adapter {
+    /// For libraries and worlds with arbitrary exports.
+    Reactor,
+
+    /// For plain programs with a `main` function, as built by
+    /// `build --command`; it exports `wasi:cli/run` itself.
+    Command,
+
+    /// For HTTP handlers targeting `wasi:http/proxy`, which only import the
+    /// interfaces provided by that world.
+    Proxy,
+}
+
+impl Adapter {
+    /// The name of the adapter, as used in its file name.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Reactor => "reactor",
+            Self::Command => "command",
+            Self::Proxy => "proxy",
+        }
+    }
+}
+
+/// A host whose supported interfaces are known to componentize-go.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum HostProfile {
+    /// Wasmtime 44 with WASI 0.2.
+    #[value(name = "wasmtime-44-p2")]
+    Wasmtime44P2,
+
+    /// Wasmtime 44 with WASI 0.2 and the WASI 0.3 release candidate it
+    /// supports.
+    #[value(name = "wasmtime-44-p3")]
+    Wasmtime44P3,
+
+    /// Spin 3 HTTP components.
+    Spin,
+
+    /// Hosts which provide only the `wasi:http/proxy@0.2.x` world, without
+    /// filesystem or socket access.
+    WasiHttpProxy,
+}
+
+impl HostProfile {
+    /// The name of the profile, as passed to `--target-profile`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Wasmtime44P2 => "wasmtime-44-p2",
+            Self::Wasmtime44P3 => "wasmtime-44-p3",
+            Self::Spin => "spin",
+            Self::WasiHttpProxy => "wasi-http-proxy",
+        }
+    }
+}
+
+fn parse_package_pin(s: &str) -> Result<(String, PathBuf)> {
+    let (package, path) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow!("expected `PACKAGE=PATH`, found `{s}`"))?;
+    Ok((package.to_string(), PathBuf::from(path)))
+}
+
+fn parse_env_var(s: &str) -> Result<(String, String)> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow!("expected `KEY=VALUE`, found `{s}`"))?;
+    Ok((key.to_string(), value.to_string()))
+}
+
 #[derive(Subcommand)]
 pub enum Command {
     /// Build a Go WebAssembly binary.
@@ -79,6 +244,95 @@ pub enum Command {
 
     /// Generate Go bindings for a WIT world.
     Bindings(Bindings),
+
+    /// Satisfy the imports of a component with the exports of other
+    /// components, like `wac plug`.
+    Compose(Compose),
+
+    /// Manage the patched Go toolchains downloaded by componentize-go.
+    Toolchain(Toolchain),
+
+    /// Print shell commands which set `GOROOT`, `PATH`, `GOOS` and `GOARCH`
+    /// for the Go toolchain that `build` would use.
+    ///
+    /// For example: `eval "$(componentize-go env)"`.
+    Env(Env),
+
+    /// Run a `go` subcommand with the Go toolchain that `build` would use,
+    /// targeting `GOOS=wasip1` and `GOARCH=wasm`.
+    ///
+    /// For example: `componentize-go go -- vet ./...`.
+    Go(Go),
+
+    /// Check the environment for common problems, such as an incompatible Go,
+    /// a missing async patch, a stale toolchain cache, or unexpected
+    /// `componentize-go.toml` worlds.
+    Doctor(Doctor),
+}
+
+/// Options controlling which Go toolchain is used.
+#[derive(clap::Args, Clone, Debug)]
+pub struct GoOpts {
+    /// The path to the Go binary (or look for binary in PATH if `None`).
+    ///
+    /// If the target WIT world uses async features, and the specified Go binary
+    /// (or the one in PATH if `None`) does not include [this
+    /// patch](https://github.com/golang/go/pull/76775), a patched version will
+    /// be downloaded, stored in the current user's [cache
+    /// directory](https://docs.rs/dirs/latest/dirs/fn.cache_dir.html), and used
+    /// instead.
+    #[arg(long)]
+    pub go: Option<PathBuf>,
+
+    /// Whether a Go toolchain with async support is required.
+    ///
+    /// By default (`auto`), async support is required only if the target
+    /// world uses async functions, futures or streams.  `require` always
+    /// requires it, while `forbid` makes any such use an error.
+    #[arg(long = "async", value_enum, default_value_t = AsyncMode::Auto)]
+    pub async_mode: AsyncMode,
+
+    /// Print additional details, such as the WIT items which require async
+    /// support.
+    #[arg(long, short = 'v')]
+    pub verbose: bool,
+
+    /// A local patched Go release archive (`.tbz`) to install if a patched Go
+    /// is needed and the one selected for this project is not yet installed.
+    #[arg(long, value_name = "FILE")]
+    pub go_archive: Option<PathBuf>,
+
+    /// Fail instead of downloading a patched Go if one is needed but not yet
+    /// installed.
+    #[arg(long)]
+    pub offline: bool,
+
+    /// How long to wait, in seconds, for another componentize-go which is
+    /// installing the same patched Go.
+    ///
+    /// Defaults to the `COMPONENTIZE_GO_LOCK_TIMEOUT` environment variable, or
+    /// 600 if unset.
+    #[arg(long, value_name = "SECONDS")]
+    pub lock_timeout: Option<u64>,
+}
+
+impl GoOpts {
+    /// Choose the Go binary to use for the specified world; see [`pick_go`].
+    fn pick_go(&self, resolve: &Resolve, world: WorldId) -> Result<PathBuf> {
+        pick_go(
+            resolve,
+            world,
+            self.go.as_deref(),
+            self.async_mode,
+            self.verbose,
+            &InstallOptions {
+                archive: self.go_archive.clone(),
+                source: None,
+                offline: self.offline,
+                lock_timeout: self.lock_timeout.map(Duration::from_secs),
+            },
+        )
+    }
 }
 
 #[derive(Parser)]
@@ -89,24 +343,124 @@ pub struct Build {
     #[arg(long)]
     pub wasip1: bool,
 
+    /// Build a plain Go program with a `main` function, rather than one using
+    /// WIT bindings, into a `wasi:cli/command` component.
+    ///
+    /// No WIT documents are needed, and the command adapter is used.
+    #[arg(long, conflicts_with_all = ["wasip1", "adapter"])]
+    pub command: bool,
+
+    /// After building, check that the component fits this world, e.g.
+    /// `wasi:http/proxy@0.2.0`: that its imports are a subset of the world's,
+    /// and its exports a superset.
+    #[arg(long, value_name = "WORLD", conflicts_with = "wasip1")]
+    pub target_world: Option<String>,
+
+    /// The WIT path in which to find `--target-world` (or the WIT used for the
+    /// build if `None`).
+    ///
+    /// This may be specified more than once.
+    #[arg(long, value_name = "PATH", requires = "target_world")]
+    pub target_wit: Vec<PathBuf>,
+
+    /// After building, check that the component only imports interfaces
+    /// provided by this host, and exports those it requires.
+    #[arg(long, value_enum, value_name = "PROFILE", conflicts_with = "wasip1")]
+    pub target_profile: Option<HostProfile>,
+
+    /// Fail if the component imports an interface matching this pattern, e.g.
+    /// `wasi:filesystem/*`, `wasi:sockets` or `wasi:cli/environment@0.2`.
+    ///
+    /// This may be specified more than once, and adds to the `deny_imports`
+    /// in the `[policy]` section of the main module's `componentize-go.toml`.
+    #[arg(long, value_name = "PATTERN", conflicts_with = "wasip1")]
+    pub deny_import: Vec<String>,
+
+    /// Fail if the component imports an interface matching none of these
+    /// patterns.
+    ///
+    /// This may be specified more than once, and adds to the `allow_imports`
+    /// in the `[policy]` section of the main module's `componentize-go.toml`.
+    #[arg(long, value_name = "PATTERN", conflicts_with = "wasip1")]
+    pub allow_import: Vec<String>,
+
+    /// Satisfy imported interfaces which the Go code can never call with a
+    /// composed component whose functions trap, so that the host need not
+    /// provide them.
+    ///
+    /// This is useful when the target world, e.g. one merged from several
+    /// `componentize-go.toml` files, imports interfaces which are not used.
+    /// Interfaces are stubbed before any `--plug` is composed.
+    #[arg(long, conflicts_with = "wasip1")]
+    pub stub_unused_imports: bool,
+
+    /// A component whose exports satisfy imports of the built component with
+    /// the same names, like `wac plug`.
+    ///
+    /// This may be specified more than once.  The plugs' own imports become
+    /// imports of the final component.
+    #[arg(long, value_name = "FILE", conflicts_with = "wasip1")]
+    pub plug: Vec<PathBuf>,
+
+    /// Validate the module and component with only these wasm features
+    /// enabled, e.g. `--wasm-features=-threads,component-model-async`.
+    ///
+    /// Features are enabled by name, or disabled if prefixed with `-`, starting
+    /// from wasmparser's defaults; `all` enables every feature.  Use this to
+    /// accept newer constructs emitted by Go, or to target a host with a
+    /// restricted feature set.  This replaces the component encoder's own
+    /// validation.
+    #[arg(
+        long,
+        value_name = "FEATURES",
+        value_delimiter = ',',
+        allow_hyphen_values = true
+    )]
+    pub wasm_features: Vec<String>,
+
+    /// An environment variable to bake into the output, like wasi-virt.
+    ///
+    /// When this is specified, possibly more than once, the program sees only
+    /// these variables, and its only argument is the name of the output file,
+    /// e.g. `main.wasm`.  The component does not import
+    /// `wasi:cli/environment`.
+    #[arg(
+        long,
+        value_name = "KEY=VALUE",
+        value_parser = parse_env_var,
+        conflicts_with = "wasip1"
+    )]
+    pub virtual_env: Vec<(String, String)>,
+
+    /// A directory of static files to bake into the output, using wasi-virt.
+    ///
+    /// The program sees the directory, read-only, as its only preopened
+    /// directory, mounted at `/`, and the component does not import
+    /// `wasi:filesystem`.  This requires `wasi-virt` in PATH, targeting the
+    /// same WASI version as the adapter.
+    #[arg(long, value_name = "DIR", conflicts_with = "wasip1")]
+    pub virtual_dir: Option<PathBuf>,
+
     /// Final output path for the component (or `./main.wasm` if `None`).
     #[arg(long, short = 'o')]
     pub output: Option<PathBuf>,
 
-    /// The path to the Go binary (or look for binary in PATH if `None`).
-    ///
-    /// If the target WIT world uses async features, and the specified Go binary
-    /// (or the one in PATH if `None`) does not include [this
-    /// patch](https://github.com/golang/go/pull/76775), a patched version will
-    /// be downloaded, stored in the current user's [cache
-    /// directory](https://docs.rs/dirs/latest/dirs/fn.cache_dir.html), and used
-    /// for building.
-    #[arg(long)]
-    pub go: Option<PathBuf>,
+    #[command(flatten)]
+    pub go_opts: GoOpts,
 
     /// The path to the snapshot adapter to convert a wasip1 module to a component (or use the embedded snapshot if `None`).
     #[arg(long)]
     pub adapt: Option<PathBuf>,
+
+    /// The kind of embedded snapshot adapter to use, unless `--adapt` is
+    /// specified.
+    ///
+    /// By default, `proxy` is used if the target world exports
+    /// `wasi:http/incoming-handler`, and `reactor` otherwise.  The `proxy`
+    /// adapter provides no environment variables, arguments or filesystem;
+    /// use `--adapter reactor` to keep them in an HTTP handler.
+    #[arg(long, value_enum, conflicts_with = "adapt")]
+    pub adapter: Option<Adapter>,
 }
 
 #[derive(Parser)]
@@ -127,26 +481,44 @@ pub struct Test {
     #[arg(long)]
     pub pkg: Vec<PathBuf>,
 
+    /// Validate the module and component with only these wasm features
+    /// enabled, e.g. `--wasm-features=-threads,component-model-async`.
+    ///
+    /// Features are enabled by name, or disabled if prefixed with `-`, starting
+    /// from wasmparser's defaults; `all` enables every feature.  Use this to
+    /// accept newer constructs emitted by Go, or to target a host with a
+    /// restricted feature set.  This replaces the component encoder's own
+    /// validation.
+    #[arg(
+        long,
+        value_name = "FEATURES",
+        value_delimiter = ',',
+        allow_hyphen_values = true
+    )]
+    pub wasm_features: Vec<String>,
+
     /// Output directory for test components (or current directory if `None`).
     ///
     /// This will be created if it does not already exist.
     #[arg(long, short = 'o')]
     pub output: Option<PathBuf>,
 
-    /// The path to the Go binary (or look for binary in PATH if `None`).
-    ///
-    /// If the target WIT world uses async features, and the specified Go binary
-    /// (or the one in PATH if `None`) does not include [this
-    /// patch](https://github.com/golang/go/pull/76775), a patched version will
-    /// be downloaded, stored in the current user's [cache
-    /// directory](https://docs.rs/dirs/latest/dirs/fn.cache_dir.html), and used
-    /// for testing.
-    #[arg(long)]
-    pub go: Option<PathBuf>,
+    #[command(flatten)]
+    pub go_opts: GoOpts,
 
     /// The path to the snapshot adapter to convert a wasip1 module to a component (or use the embedded snapshot if `None`).
     #[arg(long)]
     pub adapt: Option<PathBuf>,
+
+    /// The kind of embedded snapshot adapter to use, unless `--adapt` is
+    /// specified.
+    ///
+    /// By default, `proxy` is used if the target world exports
+    /// `wasi:http/incoming-handler`, and `reactor` otherwise.  The `proxy`
+    /// adapter provides no environment variables, arguments or filesystem;
+    /// use `--adapter reactor` to keep them in an HTTP handler.
+    #[arg(long, value_enum, conflicts_with = "adapt")]
+    pub adapter: Option<Adapter>,
 }
 
 #[derive(Parser)]
@@ -187,6 +559,141 @@ pub struct Bindings {
     /// references more than one version of the WIT package.
     #[arg(long)]
     pub include_versions: bool,
+
+    /// The path to the Go binary used to scan the current module's
+    /// dependencies for `componentize-go.toml` files (or look for binary in
+    /// PATH if `None`).
+    ///
+    /// If no Go binary is found in PATH, a previously downloaded patched Go
+    /// will be used if present; otherwise the scan is skipped.
+    #[arg(long)]
+    pub go: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+pub struct Compose {
+    /// The component whose imports should be satisfied.
+    pub socket: PathBuf,
+
+    /// A component whose exports satisfy imports of the socket component with
+    /// the same names.
+    ///
+    /// This may be specified more than once.  The plugs' own imports become
+    /// imports of the composed component.
+    #[arg(long, value_name = "FILE", required = true)]
+    pub plug: Vec<PathBuf>,
+
+    /// Output path for the composed component (or `./composed.wasm` if
+    /// `None`).
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+pub struct Env {
+    /// Ignore the target WIT world when choosing the Go toolchain, as for
+    /// `build --wasip1`.
+    #[arg(long)]
+    pub wasip1: bool,
+
+    #[command(flatten)]
+    pub go_opts: GoOpts,
+}
+
+#[derive(Parser)]
+pub struct Go {
+    /// Ignore the target WIT world when choosing the Go toolchain, as for
+    /// `build --wasip1`.
+    #[arg(long)]
+    pub wasip1: bool,
+
+    #[command(flatten)]
+    pub go_opts: GoOpts,
+
+    /// Arguments to pass to `go`.
+    #[arg(last = true, required = true)]
+    pub args: Vec<OsString>,
+}
+
+#[derive(Parser)]
+pub struct Doctor {
+    /// The path to the Go binary to check (or look for binary in PATH if
+    /// `None`).
+    #[arg(long)]
+    pub go: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+pub struct Toolchain {
+    #[command(subcommand)]
+    pub command: ToolchainCommand,
+}
+
+#[derive(Subcommand)]
+pub enum ToolchainCommand {
+    /// List installed toolchains and known releases.
+    ///
+    /// The toolchain selected for the current project is the one named by the
+    /// `toolchain` field of `./componentize-go.toml`, or else the default
+    /// release for this version of componentize-go.
+    List,
+
+    /// Download and install a toolchain, printing the path to its `go` binary.
+    ///
+    /// Releases are downloaded from the URL given by the
+    /// `COMPONENTIZE_GO_TOOLCHAIN_MIRROR` environment variable or the
+    /// `toolchain_mirror` field of `./componentize-go.toml`, or else from
+    /// GitHub.
+    Install {
+        /// The release to install, e.g. `go1.25.5-wasi-on-idle-v2` (or the
+        /// toolchain selected for the current project if `None`).
+        version: Option<String>,
+
+        /// Install from a local release archive (`.tbz`) instead of
+        /// downloading one.
+        #[arg(long, value_name = "FILE", conflicts_with = "source")]
+        from: Option<PathBuf>,
+
+        /// Build from a Go source tree, e.g. a checkout of
+        /// https://github.com/dicej/go, instead of downloading a release.
+        ///
+        /// The tree is copied and built with its `make.bash` script, which
+        /// requires an existing Go installation, found in PATH or via the
+        /// `GOROOT_BOOTSTRAP` environment variable.  This is useful on
+        /// platforms for which no release is published.
+        #[arg(long, value_name = "DIR")]
+        source: Option<PathBuf>,
+
+        /// How long to wait, in seconds, for another componentize-go which is
+        /// installing the same release.
+        ///
+        /// Defaults to the `COMPONENTIZE_GO_LOCK_TIMEOUT` environment
+        /// variable, or 600 if unset.
+        #[arg(long, value_name = "SECONDS")]
+        lock_timeout: Option<u64>,
+    },
+
+    /// Print the path to the `go` binary of an installed toolchain.
+    Path {
+        /// The release to locate (or the toolchain selected for the current
+        /// project if `None`).
+        version: Option<String>,
+    },
+
+    /// Remove an installed toolchain.
+    Remove {
+        /// The release to remove.
+        version: String,
+    },
+
+    /// Remove all toolchains other than the one selected for the current
+    /// project, as well as incomplete installations and files left behind by
+    /// earlier versions of componentize-go.
+    Prune {
+        /// Remove the selected toolchain as well.
+        #[arg(long)]
+        all: bool,
+    },
 }
 
 pub fn run<T: Into<OsString> + Clone, I: IntoIterator<Item = T>>(args: I) -> Result<()> {
@@ -195,52 +702,201 @@ pub fn run<T: Into<OsString> + Clone, I: IntoIterator<Item = T>>(args: I) -> Res
         Command::Build(opts) => build(options.wit_opts, opts),
         Command::Bindings(opts) => bindings(options.wit_opts, opts),
         Command::Test(opts) => test(options.wit_opts, opts),
+        Command::Compose(opts) => {
+            let output = opts
+                .output
+                .unwrap_or_else(|| PathBuf::from("composed.wasm"));
+            let plugged = compose(&opts.socket, &opts.plug, &output)?;
+            report_plugged(&plugged, true);
+            Ok(())
+        }
+        Command::Toolchain(opts) => toolchain(opts),
+        Command::Env(opts) => env(options.wit_opts, opts),
+        Command::Go(opts) => go(options.wit_opts, opts),
+        Command::Doctor(opts) => run_doctor(&options.wit_opts, opts.go.as_deref()),
     }
 }
 
 fn build(wit_opts: WitOpts, build: Build) -> Result<()> {
-    let (resolve, world) = if build.wasip1 {
-        dummy_wit()
+    let (resolve, world, mut config) = if build.wasip1 {
+        let (resolve, world) = dummy_wit();
+        (resolve, world, WitConfig::default())
+    } else if build.command {
+        // Only the import policy is of interest here.
+        let (resolve, world) = dummy_wit();
+        let config = config::discover(&wit_opts, build.go_opts.go.as_deref())?;
+        (resolve, world, config)
+    } else {
+        parse_wit_with_config(&wit_opts, build.go_opts.go.as_deref())?
+    };
+    for (rules, patterns) in [
+        (&mut config.allow_imports, &build.allow_import),
+        (&mut config.deny_imports, &build.deny_import),
+    ] {
+        rules.extend(patterns.iter().map(|pattern| ImportRule {
+            pattern: pattern.clone(),
+            origin: Origin::CommandLine,
+        }));
+    }
+
+    let features = if build.wasm_features.is_empty() {
+        None
     } else {
-        parse_wit(
-            &wit_opts.wit_path,
-            &wit_opts.world,
-            wit_opts.ignore_toml_files,
-            &wit_opts.features,
-            wit_opts.all_features,
-        )?
+        Some(parse_features(&build.wasm_features)?)
     };
 
-    let go = &pick_go(&resolve, world, build.go.as_deref())?;
+    let go = &build.go_opts.pick_go(&resolve, world)?;
 
     // Build a wasm module using `go build`.
-    let module = build_module(build.output.as_ref(), go, build.wasip1)?;
+    let module = build_module(build.output.as_ref(), go, build.wasip1 || build.command)?;
 
-    if !build.wasip1 {
+    if let Some(features) = features {
+        validate_features(&module, features)?;
+    }
+
+    if build.command {
+        // Wrap the module's `_start` function in a `wasi:cli/run` export.
+        module_to_component(
+            &module,
+            &resolve,
+            world,
+            build.adapt.as_deref(),
+            Adapter::Command,
+            features.is_none(),
+        )?;
+    } else if !build.wasip1 {
         // Embed the WIT documents in the wasip1 component.
         embed_wit(&module, &resolve, world)?;
 
+        // Report missing or mismatched exports in terms of WIT and Go names,
+        // rather than leaving it to the encoder.
+        check_module_exports(&module, &resolve, world)?;
+
         // Update the wasm module to use the current component model ABI.
-        module_to_component(&module, build.adapt.as_deref())?;
+        module_to_component(
+            &module,
+            &resolve,
+            world,
+            build.adapt.as_deref(),
+            adapter(build.adapter, &resolve, world),
+            features.is_none(),
+        )?;
+    }
+
+    if build.stub_unused_imports {
+        let stubbed = stub_unused_imports(&module)?;
+        eprintln!("Note: stubbed {} unused interface(s).", stubbed.len());
+        if build.go_opts.verbose {
+            for interface in &stubbed {
+                eprintln!("  {interface}");
+            }
+        }
+    }
+
+    if !build.plug.is_empty() {
+        let plugged = compose(&module, &build.plug, &module)?;
+        report_plugged(&plugged, build.go_opts.verbose);
+    }
+
+    // This follows the plugs, whose own imports of the environment and
+    // filesystem are virtualized too.
+    let virtualization = Virtualization {
+        env: (!build.virtual_env.is_empty()).then(|| build.virtual_env.clone()),
+        args: module
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .into_iter()
+            .collect(),
+        dir: build.virtual_dir.clone(),
+    };
+    if !virtualization.is_empty() {
+        let virtualized = virtualize(&module, &virtualization)?;
+        eprintln!("Note: virtualized {} import(s).", virtualized.len());
+        if build.go_opts.verbose {
+            for (import, provider) in &virtualized {
+                eprintln!("  {import} (from {provider})");
+            }
+        }
+    }
+
+    if let Some(features) = features
+        && !build.wasip1
+    {
+        validate_features(&module, features)?;
+    }
+
+    if !build.wasip1 {
+        check_import_policy(&module, &resolve, world, &config)?;
+    }
+
+    if let Some(target_world) = &build.target_world {
+        let target_opts = if build.target_wit.is_empty() {
+            WitOpts {
+                world: vec![target_world.clone()],
+                add_world: Vec::new(),
+                ..wit_opts
+            }
+        } else {
+            WitOpts {
+                wit_path: build.target_wit.clone(),
+                world: vec![target_world.clone()],
+                add_world: Vec::new(),
+                ignore_toml_files: true,
+                ..wit_opts
+            }
+        };
+        let (resolve, world) = parse_wit(&target_opts, build.go_opts.go.as_deref())?;
+        check_target_world(&module, &resolve, world)?;
+    }
+
+    if let Some(profile) = build.target_profile {
+        check_host_profile(&module, profile)?;
     }
 
     Ok(())
 }
 
+/// Print the number of imports satisfied by plugs and, if `verbose`, which
+/// plug satisfied each.
+/// The adapter to use: `explicit` if specified, or else the one chosen for
+/// `world`, noting when that is `proxy`, since earlier releases used `reactor`.
+fn adapter(explicit: Option<Adapter>, resolve: &Resolve, world: WorldId) -> Adapter {
+    explicit.unwrap_or_else(|| {
+        let adapter = select_adapter(resolve, world);
+        if let Adapter::Proxy = adapter {
+            eprintln!(
+                "Note: using the `proxy` adapter, since the world exports \
+                 `wasi:http/incoming-handler`; it provides no environment variables, arguments \
+                 or filesystem.  Pass `--adapter reactor` to keep them."
+            );
+        }
+        adapter
+    })
+}
+
+fn report_plugged(plugged: &BTreeMap<String, String>, verbose: bool) {
+    eprintln!("Note: plugged {} import(s).", plugged.len());
+    if verbose {
+        for (import, plug) in plugged {
+            eprintln!("  {import} (from {plug})");
+        }
+    }
+}
+
 fn test(wit_opts: WitOpts, test: Test) -> Result<()> {
     let (resolve, world) = if test.wasip1 {
         dummy_wit()
     } else {
-        parse_wit(
-            &wit_opts.wit_path,
-            &wit_opts.world,
-            wit_opts.ignore_toml_files,
-            &wit_opts.features,
-            wit_opts.all_features,
-        )?
+        parse_wit(&wit_opts, test.go_opts.go.as_deref())?
+    };
+
+    let features = if test.wasm_features.is_empty() {
+        None
+    } else {
+        Some(parse_features(&test.wasm_features)?)
     };
 
-    let go = &pick_go(&resolve, world, test.go.as_deref())?;
+    let go = &test.go_opts.pick_go(&resolve, world)?;
 
     if test.pkg.is_empty() {
         return Err(anyhow!("Path to a package containing Go tests is required"));
@@ -250,12 +906,28 @@ fn test(wit_opts: WitOpts, test: Test) -> Result<()> {
         // Build a wasm module using `go test -c`.
         let module = build_test_module(pkg, test.output.as_ref(), go, test.wasip1)?;
 
+        if let Some(features) = features {
+            validate_features(&module, features)?;
+        }
+
         if !test.wasip1 {
             // Embed the WIT documents in the wasm module.
             embed_wit(&module, &resolve, world)?;
+            check_module_exports(&module, &resolve, world)?;
 
             // Update the wasm module to use the current component model ABI.
-            module_to_component(&module, test.adapt.as_deref())?;
+            module_to_component(
+                &module,
+                &resolve,
+                world,
+                test.adapt.as_deref(),
+                adapter(test.adapter, &resolve, world),
+                features.is_none(),
+            )?;
+
+            if let Some(features) = features {
+                validate_features(&module, features)?;
+            }
         }
     }
 
@@ -263,13 +935,7 @@ fn test(wit_opts: WitOpts, test: Test) -> Result<()> {
 }
 
 fn bindings(wit_opts: WitOpts, bindings: Bindings) -> Result<()> {
-    let (mut resolve, world) = parse_wit(
-        &wit_opts.wit_path,
-        &wit_opts.world,
-        wit_opts.ignore_toml_files,
-        &wit_opts.features,
-        wit_opts.all_features,
-    )?;
+    let (mut resolve, world) = parse_wit(&wit_opts, bindings.go.as_deref())?;
 
     generate_bindings(
         &mut resolve,
@@ -282,3 +948,71 @@ fn bindings(wit_opts: WitOpts, bindings: Bindings) -> Result<()> {
         bindings.include_versions,
     )
 }
+
+fn env(wit_opts: WitOpts, env: Env) -> Result<()> {
+    let (resolve, world) = if env.wasip1 {
+        dummy_wit()
+    } else {
+        parse_wit(&wit_opts, env.go_opts.go.as_deref())?
+    };
+
+    let go = &env.go_opts.pick_go(&resolve, world)?;
+
+    for (key, value) in go_env_vars(go)? {
+        println!("export {key}={}", shell_quote(&value.to_string_lossy()));
+    }
+
+    Ok(())
+}
+
+fn go(wit_opts: WitOpts, go: Go) -> Result<()> {
+    let (resolve, world) = if go.wasip1 {
+        dummy_wit()
+    } else {
+        parse_wit(&wit_opts, go.go_opts.go.as_deref())?
+    };
+
+    let bin = &go.go_opts.pick_go(&resolve, world)?;
+
+    let status = process::Command::new(bin)
+        .args(&go.args)
+        .envs(go_env_vars(bin)?)
+        .status()
+        .with_context(|| format!("failed to run `{}`", bin.display()))?;
+
+    if !status.success() {
+        // Pass the exit code along so that scripts can react to e.g. `go vet`
+        // failures as usual.
+        process::exit(status.code().unwrap_or(1));
+    }
+
+    Ok(())
+}
+
+/// Quote `s` for use in a POSIX shell.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+fn toolchain(toolchain: Toolchain) -> Result<()> {
+    match toolchain.command {
+        ToolchainCommand::List => list_toolchains(),
+        ToolchainCommand::Install {
+            version,
+            from,
+            source,
+            lock_timeout,
+        } => install_toolchain(
+            version.as_deref(),
+            InstallOptions {
+                archive: from,
+                source,
+                offline: false,
+                lock_timeout: lock_timeout.map(Duration::from_secs),
+            },
+        ),
+        ToolchainCommand::Path { version } => toolchain_path(version.as_deref()),
+        ToolchainCommand::Remove { version } => remove_toolchain(&version),
+        ToolchainCommand::Prune { all } => prune_toolchains(all),
+    }
+}
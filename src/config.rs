@@ -0,0 +1,718 @@
+use crate::{
+    command::{Discovery, WitOpts},
+    gomod::{self, GoModule},
+    utils::discovery_go,
+};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    time::UNIX_EPOCH,
+};
+
+/// The name of the file `componentize-go` looks for in the root of the current
+/// Go module and each of its dependencies.
+pub const CONFIG_FILE_NAME: &str = "componentize-go.toml";
+
+/// The contents of a `componentize-go.toml` file.
+#[derive(Deserialize, Default, Debug)]
+pub struct ComponentizeGoConfig {
+    /// The fully-qualified names of the WIT worlds to target by default.
+    #[serde(default)]
+    pub worlds: Vec<String>,
+
+    /// The paths, relative to the module root, in which the WIT files are
+    /// stored.
+    #[serde(default)]
+    pub wit_paths: Vec<String>,
+
+    /// Module paths of Go dependencies whose `componentize-go.toml` files
+    /// should be ignored.
+    ///
+    /// This is only honored in the main module's file.
+    #[serde(default)]
+    pub exclude_modules: Vec<String>,
+
+    /// Map of WIT package names (with or without a version) to the WIT path,
+    /// relative to the module root, which should supply that package.
+    ///
+    /// This is only honored in the main module's file.
+    #[serde(default)]
+    pub packages: BTreeMap<String, String>,
+
+    /// Versioned WIT package names, e.g. `wasi:io@0.2.0`, to use instead of
+    /// the highest semver-compatible version found.
+    ///
+    /// This is only honored in the main module's file.
+    #[serde(default)]
+    pub package_versions: Vec<String>,
+
+    /// The patched Go release to use when a downloaded toolchain is needed,
+    /// e.g. `go1.25.5-wasi-on-idle-v2`.
+    ///
+    /// This is only honored in the main module's file.
+    pub toolchain: Option<String>,
+
+    /// The base URL from which patched Go releases are downloaded, e.g. an
+    /// internal mirror of `https://github.com/dicej/go/releases/download`.
+    ///
+    /// This is only honored in the main module's file, and is overridden by
+    /// the `COMPONENTIZE_GO_TOOLCHAIN_MIRROR` environment variable.
+    pub toolchain_mirror: Option<String>,
+
+    /// Rules restricting which interfaces the built component may import.
+    ///
+    /// This is only honored in the main module's file.
+    #[serde(default)]
+    pub policy: Policy,
+}
+
+/// The `[policy]` section of a `componentize-go.toml` file.
+#[derive(Deserialize, Default, Debug)]
+pub struct Policy {
+    /// Interface patterns, e.g. `wasi:cli/*` or `wasi:http/types@0.2`, at
+    /// least one of which each import must match, if any are given.
+    #[serde(default)]
+    pub allow_imports: Vec<String>,
+
+    /// Interface patterns, e.g. `wasi:filesystem/*`, which no import may
+    /// match.
+    #[serde(default)]
+    pub deny_imports: Vec<String>,
+}
+
+impl ComponentizeGoConfig {
+    /// Read and parse the `componentize-go.toml` file in `dir`, if present.
+    pub fn read(dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(CONFIG_FILE_NAME);
+        let manifest = match fs::read_to_string(&path) {
+            Ok(manifest) => manifest,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read `{}`", path.display()));
+            }
+        };
+        toml::from_str(&manifest)
+            .with_context(|| format!("failed to parse `{}`", path.display()))
+            .map(Some)
+    }
+}
+
+/// Where a WIT path, world, package pin, or import rule came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Origin {
+    /// Specified via a command line option.
+    CommandLine,
+    /// Specified in the `componentize-go.toml` file of the named Go module.
+    Module(String),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::CommandLine => write!(f, "the command line"),
+            Origin::Module(module) => write!(f, "Go module `{module}`"),
+        }
+    }
+}
+
+/// A WIT directory or file to be loaded.
+#[derive(Clone, Debug)]
+pub struct WitSource {
+    pub path: PathBuf,
+    pub origin: Origin,
+}
+
+/// A request to have a specific WIT path supply a package which may be
+/// defined in more than one place.
+#[derive(Clone, Debug)]
+pub struct PackagePin {
+    /// The package name, e.g. `wasi:io@0.2.0`, or `wasi:io` for all versions.
+    pub package: String,
+    pub path: PathBuf,
+    pub origin: Origin,
+}
+
+/// A request to use a specific version of a WIT package rather than the
+/// highest semver-compatible version found.
+#[derive(Clone, Debug)]
+pub struct PackageVersion {
+    /// The versioned package name, e.g. `wasi:io@0.2.0`.
+    pub package: String,
+    pub origin: Origin,
+}
+
+/// A pattern allowing or denying imports of the matching interfaces.
+#[derive(Clone, Debug)]
+pub struct ImportRule {
+    /// The interface pattern, e.g. `wasi:filesystem/*`.
+    pub pattern: String,
+    pub origin: Origin,
+}
+
+/// The WIT paths, worlds, package pins, package versions and import rules to
+/// use after combining the command line options with any
+/// `componentize-go.toml` files.
+#[derive(Debug, Default)]
+pub struct WitConfig {
+    pub sources: Vec<WitSource>,
+    pub worlds: Vec<String>,
+    /// Where each of `worlds` came from.
+    pub world_origins: BTreeMap<String, Origin>,
+    pub pins: Vec<PackagePin>,
+    pub versions: Vec<PackageVersion>,
+    pub allow_imports: Vec<ImportRule>,
+    pub deny_imports: Vec<ImportRule>,
+}
+
+impl WitConfig {
+    fn add_source(&mut self, path: PathBuf, origin: Origin) {
+        if !self.sources.iter().any(|source| source.path == path) {
+            self.sources.push(WitSource { path, origin });
+        }
+    }
+
+    fn add_policy(&mut self, policy: &Policy, origin: Origin) {
+        for (rules, patterns) in [
+            (&mut self.allow_imports, &policy.allow_imports),
+            (&mut self.deny_imports, &policy.deny_imports),
+        ] {
+            rules.extend(patterns.iter().map(|pattern| ImportRule {
+                pattern: pattern.clone(),
+                origin: origin.clone(),
+            }));
+        }
+    }
+
+    fn add_world(&mut self, world: &str, origin: Origin) {
+        if !self.worlds.iter().any(|w| w == world) {
+            self.worlds.push(world.to_string());
+            self.world_origins.insert(world.to_string(), origin);
+        }
+    }
+}
+
+/// Combine the WIT paths, worlds, package pins and package versions specified
+/// on the command line with those found in `componentize-go.toml` files.
+///
+/// Unless `--ignore-toml-files` is specified, the current module and its
+/// dependencies are searched for `componentize-go.toml` files, using either
+/// `go list` or, with `--discovery go-mod`, the `go.mod` file and module cache.  The
+/// WIT paths in each such file are always added, while its worlds are only
+/// added if no `--world` option was given.  The main module's file may
+/// additionally exclude dependencies, pin packages to specific WIT paths,
+/// choose package versions, and restrict the component's imports.
+///
+/// The import restrictions of the `componentize-go.toml` file in the current
+/// directory are always applied, even with `--ignore-toml-files` or when no Go
+/// binary is available for the search, since skipping them would silently
+/// disable the check.
+///
+/// `go_path` is the Go binary specified on the command line, if any; see
+/// [`discovery_go`] for how a binary is chosen otherwise.
+pub fn discover(wit_opts: &WitOpts, go_path: Option<&Path>) -> Result<WitConfig> {
+    let mut config = WitConfig::default();
+
+    for path in &wit_opts.wit_path {
+        config.add_source(path.clone(), Origin::CommandLine);
+    }
+    for world in wit_opts.world.iter().chain(&wit_opts.add_world) {
+        config.add_world(world, Origin::CommandLine);
+    }
+    for (package, path) in &wit_opts.pin_package {
+        config.pins.push(PackagePin {
+            package: package.clone(),
+            path: path.clone(),
+            origin: Origin::CommandLine,
+        });
+    }
+    for package in &wit_opts.package_version {
+        config.versions.push(PackageVersion {
+            package: package.clone(),
+            origin: Origin::CommandLine,
+        });
+    }
+
+    let main_dir = env::current_dir().context("failed to get current directory")?;
+    add_main_policy(&mut config, &main_dir)?;
+
+    if wit_opts.ignore_toml_files || !Path::new("go.mod").exists() {
+        return Ok(config);
+    }
+
+    // Only add worlds from `componentize-go.toml` files if none were specified
+    // explicitly via `--world`:
+    let add_worlds = wit_opts.world.is_empty();
+
+    let modules = match wit_opts.discovery {
+        Discovery::GoList => modules_with_config(go_path)?,
+        Discovery::GoMod => with_config(gomod::list_modules()?)?,
+    };
+
+    let mut excluded = wit_opts
+        .exclude_module
+        .iter()
+        .cloned()
+        .collect::<BTreeSet<_>>();
+    for (module, file) in modules.iter().filter(|(module, _)| module.main) {
+        excluded.extend(file.exclude_modules.iter().cloned());
+        for (package, path) in &file.packages {
+            config.pins.push(PackagePin {
+                package: package.clone(),
+                path: module.dir.join(path),
+                origin: Origin::Module(module.path.clone()),
+            });
+        }
+        for package in &file.package_versions {
+            config.versions.push(PackageVersion {
+                package: package.clone(),
+                origin: Origin::Module(module.path.clone()),
+            });
+        }
+        // The current module's policy has already been added above, but
+        // those of the other modules of a workspace are only found here.
+        if !same_dir(&module.dir, &main_dir) {
+            config.add_policy(&file.policy, Origin::Module(module.path.clone()));
+        }
+    }
+
+    for (module, file) in &modules {
+        if excluded.contains(&module.path) {
+            continue;
+        }
+        if add_worlds {
+            for world in &file.worlds {
+                config.add_world(world, Origin::Module(module.path.clone()));
+            }
+        }
+        for path in &file.wit_paths {
+            config.add_source(module.dir.join(path), Origin::Module(module.path.clone()));
+        }
+    }
+
+    // Catch typos, since an exclusion which matches nothing is otherwise
+    // silently ignored.
+    for module in &excluded {
+        if !modules.iter().any(|(m, _)| &m.path == module) {
+            eprintln!(
+                "Note: excluded module `{module}` does not have a `{CONFIG_FILE_NAME}` file \
+                 among the current module's dependencies."
+            );
+        }
+    }
+
+    Ok(config)
+}
+
+/// Add the import rules from the `[policy]` section of the
+/// `componentize-go.toml` file in `dir`, the root of the main module, if the
+/// file exists.
+///
+/// Unlike the rest of the file, this is read directly rather than via module
+/// discovery, so that the policy holds regardless of how discovery fares.
+fn add_main_policy(config: &mut WitConfig, dir: &Path) -> Result<()> {
+    let Some(file) = ComponentizeGoConfig::read(dir)? else {
+        return Ok(());
+    };
+    let go_mod = dir.join("go.mod");
+    let module = if go_mod.exists() {
+        gomod::GoModFile::read(&go_mod)?.module
+    } else {
+        None
+    };
+    let module = module.unwrap_or_else(|| dir.display().to_string());
+    config.add_policy(&file.policy, Origin::Module(module));
+    Ok(())
+}
+
+/// Whether `a` and `b` name the same directory.
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Find the current module and those of its dependencies which have a
+/// `componentize-go.toml` file, along with the parsed contents of that file.
+///
+/// Since `go list` can be slow for large module graphs, the results are
+/// cached, keyed on the Go toolchain, the `GOFLAGS`, `GOOS`, `GOARCH` and
+/// `GOTOOLCHAIN` settings, and the contents of `go.mod`, `go.sum` and
+/// `go.work`.
+fn modules_with_config(go_path: Option<&Path>) -> Result<Vec<(GoModule, ComponentizeGoConfig)>> {
+    let go = discovery_go(go_path)?;
+    let key = project_key(go.as_deref());
+    let cache_path = DiscoveryCache::path();
+
+    let modules = match cache_path
+        .as_deref()
+        .and_then(|path| DiscoveryCache::load(path, &key))
+    {
+        Some(cache) => cache.modules,
+        None => {
+            let Some(go) = go else {
+                eprintln!(
+                    "Note: `go` command not found; skipping the search for `{CONFIG_FILE_NAME}` \
+                     files in the current module's dependencies.  Use `--go` to specify a Go \
+                     binary, or `--ignore-toml-files` to silence this message."
+                );
+                return Ok(Vec::new());
+            };
+            let modules = list_modules(&go)?
+                .into_iter()
+                .filter(|module| {
+                    module.main || module.replaced || module.dir.join(CONFIG_FILE_NAME).exists()
+                })
+                .collect::<Vec<_>>();
+            if let Some(path) = &cache_path {
+                DiscoveryCache::new(key, modules.clone()).store(path);
+            }
+            modules
+        }
+    };
+
+    with_config(modules)
+}
+
+/// Pair each module which has a `componentize-go.toml` file with its parsed
+/// contents.
+fn with_config(modules: Vec<GoModule>) -> Result<Vec<(GoModule, ComponentizeGoConfig)>> {
+    modules
+        .into_iter()
+        .filter_map(|module| match ComponentizeGoConfig::read(&module.dir) {
+            Ok(Some(file)) => Some(Ok((module, file))),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        })
+        .collect()
+}
+
+/// The results of scanning the current module's dependencies for
+/// `componentize-go.toml` files.
+#[derive(Serialize, Deserialize)]
+struct DiscoveryCache {
+    /// The version of `componentize-go` which wrote the cache.
+    version: String,
+    /// A hash of the Go toolchain and settings; see [`project_key`].
+    key: String,
+    /// Modules which have a `componentize-go.toml` file, plus any main or
+    /// replaced modules, which may gain one at any time.
+    modules: Vec<GoModule>,
+    /// The state of the `go.mod`, `go.sum` and `go.work` files, of the
+    /// `componentize-go.toml` files of the above modules and of the WIT paths
+    /// they reference.
+    files: BTreeMap<PathBuf, FileState>,
+}
+
+impl DiscoveryCache {
+    fn new(key: String, modules: Vec<GoModule>) -> Self {
+        let mut files = project_files()
+            .into_iter()
+            .map(|path| {
+                let state = FileState::new(&path);
+                (path, state)
+            })
+            .collect::<BTreeMap<_, _>>();
+        for module in &modules {
+            let path = module.dir.join(CONFIG_FILE_NAME);
+            if let Ok(Some(config)) = ComponentizeGoConfig::read(&module.dir) {
+                for wit_path in config.wit_paths {
+                    let wit_path = module.dir.join(wit_path);
+                    files.insert(wit_path.clone(), FileState::new(&wit_path));
+                }
+            }
+            files.insert(path.clone(), FileState::new(&path));
+        }
+
+        Self {
+            version: env!("CARGO_PKG_VERSION").into(),
+            key,
+            modules,
+            files,
+        }
+    }
+
+    /// The location of the cache for the current directory, if any.
+    fn path() -> Option<PathBuf> {
+        let dir = env::current_dir().ok()?;
+        let dir = fs::canonicalize(&dir).unwrap_or(dir);
+        let mut digest = Digest::new();
+        digest.add(Some(dir.as_os_str().as_encoded_bytes()));
+        let name = digest.finish();
+        Some(
+            dirs::cache_dir()?
+                .join("componentize-go")
+                .join("discovery")
+                .join(format!("{name}.toml")),
+        )
+    }
+
+    /// Load the cache from `path`, provided it's still valid.
+    fn load(path: &Path, key: &str) -> Option<Self> {
+        let cache = toml::from_str::<Self>(&fs::read_to_string(path).ok()?).ok()?;
+        (cache.version == env!("CARGO_PKG_VERSION")
+            && cache.key == key
+            && project_files()
+                .iter()
+                .all(|path| cache.files.contains_key(path))
+            && cache.files.iter().all(|(path, state)| state.matches(path)))
+        .then_some(cache)
+    }
+
+    /// Store the cache to `path`, ignoring any errors since the cache is only
+    /// an optimization.
+    fn store(&self, path: &Path) {
+        if let (Some(dir), Ok(contents)) = (path.parent(), toml::to_string(self)) {
+            _ = fs::create_dir_all(dir).and_then(|()| fs::write(path, contents));
+        }
+    }
+}
+
+/// The `go.mod`, `go.sum`, `go.work` and `go.work.sum` files which, along
+/// with the settings in [`project_key`], determine the results of
+/// `go list -m all`, whether or not they exist.
+fn project_files() -> Vec<PathBuf> {
+    let mut files = vec![PathBuf::from("go.mod"), PathBuf::from("go.sum")];
+    if let Some(go_work) = gomod::go_work_path() {
+        let mut sum = go_work.clone().into_os_string();
+        sum.push(".sum");
+        files.extend([go_work, sum.into()]);
+    }
+    files
+}
+
+/// Hash the Go toolchain `go`, if any, and the `GOFLAGS`, `GOOS`, `GOARCH`
+/// and `GOTOOLCHAIN` settings, which along with the [`project_files`]
+/// determine the results of `go list -m all`.
+///
+/// The toolchain is identified by its path and the `VERSION` file of its
+/// `GOROOT`, or failing that the size and modification time of the binary,
+/// so that `go` need not be run.
+fn project_key(go: Option<&Path>) -> String {
+    let mut digest = Digest::new();
+    let go = go.map(|go| fs::canonicalize(go).unwrap_or_else(|_| go.to_owned()));
+    digest.add(go.as_ref().map(|go| go.as_os_str().as_encoded_bytes()));
+    let version = go
+        .as_ref()
+        .and_then(|go| go.parent()?.parent())
+        .and_then(|goroot| fs::read(goroot.join("VERSION")).ok());
+    match version {
+        Some(version) => digest.add(Some(&version)),
+        None => digest.add(go.as_ref().and_then(|go| stamp(go)).as_deref()),
+    }
+    for key in ["GOFLAGS", "GOOS", "GOARCH", "GOTOOLCHAIN"] {
+        digest.add(Some(key.as_bytes()));
+        digest.add(gomod::go_env(key).as_deref().map(str::as_bytes));
+    }
+    digest.finish()
+}
+
+/// What a cache remembers of a file or directory, to tell whether it changed.
+#[derive(Serialize, Deserialize)]
+struct FileState {
+    /// A hash of the sizes and modification times, which is cheap to check.
+    metadata: String,
+    /// A hash of the contents, which is checked only if the metadata differs.
+    contents: String,
+}
+
+impl FileState {
+    fn new(path: &Path) -> Self {
+        Self {
+            metadata: fingerprint(path, Scan::Metadata),
+            contents: fingerprint(path, Scan::Contents),
+        }
+    }
+
+    /// Whether the file or directory at `path` is unchanged.
+    fn matches(&self, path: &Path) -> bool {
+        fingerprint(path, Scan::Metadata) == self.metadata
+            || fingerprint(path, Scan::Contents) == self.contents
+    }
+}
+
+/// What [`fingerprint`] hashes of each file.
+#[derive(Clone, Copy)]
+enum Scan {
+    Metadata,
+    Contents,
+}
+
+/// Hash the names and either the metadata or the contents of the file or
+/// directory (recursively) at `path`, or return an empty string if it does
+/// not exist.
+fn fingerprint(path: &Path, scan: Scan) -> String {
+    fn visit(path: &Path, scan: Scan, digest: &mut Digest) {
+        if path.is_dir() {
+            let mut entries = fs::read_dir(path)
+                .into_iter()
+                .flatten()
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .collect::<Vec<_>>();
+            entries.sort();
+            for entry in entries {
+                digest.add(entry.file_name().map(|name| name.as_encoded_bytes()));
+                visit(&entry, scan, digest);
+            }
+        } else {
+            match scan {
+                Scan::Metadata => digest.add(stamp(path).as_deref()),
+                Scan::Contents => digest.add(fs::read(path).ok().as_deref()),
+            }
+        }
+    }
+
+    if !path.exists() {
+        return String::new();
+    }
+    let mut digest = Digest::new();
+    visit(path, scan, &mut digest);
+    digest.finish()
+}
+
+/// The size and modification time of the file at `path`.
+fn stamp(path: &Path) -> Option<Vec<u8>> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_nanos();
+    Some([&metadata.len().to_le_bytes()[..], &modified.to_le_bytes()].concat())
+}
+
+/// A SHA-256 digest of a sequence of fields, which unlike `std::hash` is
+/// stable across Rust releases and platforms, so that cache keys remain valid.
+struct Digest(ring::digest::Context);
+
+impl Digest {
+    fn new() -> Self {
+        Self(ring::digest::Context::new(&ring::digest::SHA256))
+    }
+
+    /// Add a field, or `None` for one which is absent, e.g. a missing file.
+    ///
+    /// Fields are length-prefixed so that no two sequences of fields collide.
+    fn add(&mut self, field: Option<&[u8]>) {
+        match field {
+            Some(bytes) => {
+                self.0.update(&[1]);
+                self.0.update(&(bytes.len() as u64).to_le_bytes());
+                self.0.update(bytes);
+            }
+            None => self.0.update(&[0]),
+        }
+    }
+
+    /// The lower-case hexadecimal digest.
+    fn finish(self) -> String {
+        self.0
+            .finish()
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+}
+
+/// Use `go list` to find the current module and all of its dependencies.
+fn list_modules(go: &Path) -> Result<Vec<GoModule>> {
+    let output = Command::new(go)
+        .args([
+            "list",
+            "-mod=readonly",
+            "-m",
+            "-f",
+            "{{.Path}}\t{{.Main}}\t{{if .Replace}}true{{else}}false{{end}}\t{{.Dir}}",
+            "all",
+        ])
+        .output()
+        .with_context(|| format!("failed to run `{} list`", go.display()))?;
+    if !output.status.success() {
+        bail!(
+            "`go list` failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    Ok(String::from_utf8(output.stdout)?
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(4, '\t');
+            let path = fields.next()?;
+            let main = fields.next()? == "true";
+            let replaced = fields.next()? == "true";
+            let dir = fields.next()?;
+            // Modules which have not been downloaded have no directory.
+            (!dir.is_empty()).then(|| GoModule {
+                path: path.to_string(),
+                dir: PathBuf::from(dir),
+                main,
+                replaced,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs::File, time::Duration};
+
+    #[test]
+    fn test_digest() {
+        let digest = |fields: &[Option<&[u8]>]| {
+            let mut digest = Digest::new();
+            for field in fields {
+                digest.add(*field);
+            }
+            digest.finish()
+        };
+
+        // The digest is SHA-256, so it's stable across builds.
+        assert_eq!(
+            digest(&[]),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(digest(&[Some(b"go.mod")]), digest(&[Some(b"go.mod")]));
+
+        // Field boundaries and absent fields are significant.
+        assert_ne!(
+            digest(&[Some(b"ab"), Some(b"c")]),
+            digest(&[Some(b"a"), Some(b"bc")])
+        );
+        assert_ne!(digest(&[None]), digest(&[Some(b"")]));
+        assert_ne!(digest(&[None]), digest(&[]));
+    }
+
+    #[test]
+    fn test_file_state() {
+        let dir = crate::testing::temp_path("state");
+        fs::create_dir_all(dir.join("wit")).unwrap();
+        let file = dir.join("wit/world.wit");
+        fs::write(&file, "package a:b;\n").unwrap();
+        let state = FileState::new(&dir);
+        assert!(state.matches(&dir));
+
+        // Touching a file changes its metadata but not its contents.
+        let modified = fs::metadata(&file).unwrap().modified().unwrap();
+        File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(modified + Duration::from_secs(1))
+            .unwrap();
+        assert_ne!(fingerprint(&dir, Scan::Metadata), state.metadata);
+        assert!(state.matches(&dir));
+
+        fs::write(&file, "package a:c;\n").unwrap();
+        let matches = state.matches(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(!matches);
+    }
+}
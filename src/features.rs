@@ -0,0 +1,159 @@
+use anyhow::{Result, anyhow, bail};
+use std::{collections::BTreeMap, fs, path::Path};
+use wasmparser::{
+    BinaryReaderError, Encoding, KnownCustom, Name, Parser, Payload, TypeRef, Validator,
+    WasmFeatures,
+};
+
+/// Parse a list of wasm features in the manner of `wasm-tools --features`,
+/// starting from wasmparser's defaults.
+///
+/// Each item names a feature to enable, e.g. `threads`, or to disable if
+/// prefixed with `-`, e.g. `-gc`.  `all` enables every feature and `-all`
+/// disables every feature.  Component model features may be given as e.g.
+/// `component-model-async` or `cm-async`.
+pub fn parse_features(specs: &[String]) -> Result<WasmFeatures> {
+    let mut features = WasmFeatures::default();
+    for spec in specs {
+        let (enable, name) = match spec.strip_prefix('-') {
+            Some(name) => (false, name),
+            None => (true, spec.strip_prefix('+').unwrap_or(spec)),
+        };
+        let flags = if name == "all" {
+            WasmFeatures::all()
+        } else {
+            let flag =
+                name.to_ascii_uppercase()
+                    .replace('-', "_")
+                    .replacen("COMPONENT_MODEL_", "CM_", 1);
+            WasmFeatures::from_name(&flag).ok_or_else(|| {
+                anyhow!(
+                    "unknown wasm feature `{name}`; expected one of: all, {}",
+                    feature_names().join(", ")
+                )
+            })?
+        };
+        features.set(flags, enable);
+    }
+    Ok(features)
+}
+
+/// The names accepted by [`parse_features`] for each feature.
+fn feature_names() -> Vec<String> {
+    WasmFeatures::all()
+        .iter_names()
+        .map(|(name, _)| {
+            let name = name.to_ascii_lowercase().replace('_', "-");
+            match name.strip_prefix("cm-") {
+                Some(name) => format!("component-model-{name}"),
+                None => name,
+            }
+        })
+        .collect()
+}
+
+/// Validate the module or component in `wasm_file` with only `features`
+/// enabled.
+///
+/// If validation fails within a function, the error names the index of the
+/// function within its core module and, if the module has a name section as Go
+/// emits, the function's symbol name, e.g. `main.main`.
+pub fn validate_features(wasm_file: &Path, features: WasmFeatures) -> Result<()> {
+    let wasm = fs::read(wasm_file)?;
+    let Err(error) = Validator::new_with_features(features).validate_all(&wasm) else {
+        return Ok(());
+    };
+
+    let location = match find_function(&wasm, &error) {
+        Some((index, Some(name))) => format!(" in function {index} (`{name}`)"),
+        Some((index, None)) => format!(" in function {index}"),
+        None => String::new(),
+    };
+    bail!(
+        "`{}` is invalid with the selected wasm features{location}: {error}",
+        wasm_file.display()
+    )
+}
+
+/// The functions of a core module, in the order of the code section.
+#[derive(Default)]
+struct Functions {
+    imports: u32,
+    bodies: Vec<std::ops::Range<usize>>,
+    names: BTreeMap<u32, String>,
+}
+
+/// Find the index and, if known, the name of the function whose body contains
+/// the offset of `error`.
+fn find_function(wasm: &[u8], error: &BinaryReaderError) -> Option<(u32, Option<String>)> {
+    let offset = error.offset();
+    // Components may contain several core modules, e.g. for adapters, so keep
+    // track of the module being parsed.
+    let mut module = None::<Functions>;
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload.ok()? {
+            Payload::Version {
+                encoding: Encoding::Module,
+                ..
+            } => module = Some(Functions::default()),
+            Payload::ImportSection(reader) => {
+                let module = module.as_mut()?;
+                for import in reader.into_imports() {
+                    if let TypeRef::Func(_) | TypeRef::FuncExact(_) = import.ok()?.ty {
+                        module.imports += 1;
+                    }
+                }
+            }
+            Payload::CodeSectionEntry(body) => module.as_mut()?.bodies.push(body.range()),
+            Payload::CustomSection(reader) => {
+                if let (Some(module), KnownCustom::Name(reader)) = (&mut module, reader.as_known())
+                {
+                    for name in reader.into_iter().flatten() {
+                        if let Name::Function(map) = name {
+                            for naming in map.into_iter().flatten() {
+                                module.names.insert(naming.index, naming.name.to_string());
+                            }
+                        }
+                    }
+                }
+            }
+            Payload::End(_) => {
+                if let Some(module) = module.take()
+                    && let Some(body) = module
+                        .bodies
+                        .iter()
+                        .position(|range| range.contains(&offset))
+                {
+                    let index = module.imports + body as u32;
+                    return Some((index, module.names.get(&index).cloned()));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_features() {
+        let features = parse_features(&[
+            "-threads".to_string(),
+            "component-model-async".to_string(),
+            "cm-error-context".to_string(),
+        ])
+        .unwrap();
+        assert!(!features.threads());
+        assert!(features.cm_async());
+        assert!(features.cm_error_context());
+        assert!(features.gc());
+
+        let features = parse_features(&["-all".to_string(), "simd".to_string()]).unwrap();
+        assert_eq!(features, WasmFeatures::SIMD);
+
+        assert!(parse_features(&["nonsense".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,613 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// A module in the current module's build list.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GoModule {
+    pub path: String,
+    pub dir: PathBuf,
+    pub main: bool,
+    /// Whether the module is subject to a `replace` directive, in which case
+    /// its directory may be edited in place.
+    pub replaced: bool,
+}
+
+/// The directives of a `go.mod` or `go.work` file which `componentize-go`
+/// cares about.
+#[derive(Default, Debug, PartialEq)]
+pub struct GoModFile {
+    pub module: Option<String>,
+    pub go: Option<String>,
+    pub toolchain: Option<String>,
+    /// Module paths and versions from `require` directives.
+    pub requires: Vec<(String, String)>,
+    pub replaces: Vec<Replace>,
+    /// Directories from `use` directives (`go.work` only).
+    pub uses: Vec<String>,
+}
+
+/// A `replace` directive.
+#[derive(Debug, PartialEq)]
+pub struct Replace {
+    pub path: String,
+    /// The version being replaced, or `None` for all versions.
+    pub version: Option<String>,
+    pub replacement: Replacement,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Replacement {
+    /// A local directory, relative to the file containing the directive.
+    Dir(String),
+    /// A module path and version.
+    Module(String, String),
+}
+
+impl GoModFile {
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read `{}`", path.display()))?;
+        Self::parse(&contents).with_context(|| format!("failed to parse `{}`", path.display()))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut file = Self::default();
+        let mut block = None::<String>;
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.split("//").next().unwrap_or_default().trim();
+            let mut tokens = line
+                .split_whitespace()
+                .map(|token| token.trim_matches(|c| c == '"' || c == '`'))
+                .collect::<Vec<_>>();
+
+            match (&block, tokens.as_slice()) {
+                (_, []) => continue,
+                (Some(_), [")"]) => {
+                    block = None;
+                    continue;
+                }
+                (Some(verb), _) => tokens.insert(0, verb),
+                (None, [verb, "("]) => {
+                    block = Some(verb.to_string());
+                    continue;
+                }
+                (None, _) => {}
+            }
+
+            let bad = || format!("line {}: unexpected `{line}`", number + 1);
+            match tokens[..] {
+                ["module", path] => file.module = Some(path.to_string()),
+                ["go", version] => file.go = Some(version.to_string()),
+                ["toolchain", name] => file.toolchain = Some(name.to_string()),
+                ["require", path, version, ..] => {
+                    file.requires.push((path.to_string(), version.to_string()))
+                }
+                ["use", dir] => file.uses.push(dir.to_string()),
+                ["replace", ref rest @ ..] => {
+                    let arrow = rest.iter().position(|&t| t == "=>");
+                    let (old, new) = match arrow {
+                        Some(arrow) => (&rest[..arrow], &rest[arrow + 1..]),
+                        None => bail!(bad()),
+                    };
+                    let (path, version) = match *old {
+                        [path] => (path, None),
+                        [path, version] => (path, Some(version.to_string())),
+                        _ => bail!(bad()),
+                    };
+                    let replacement = match *new {
+                        [dir] => Replacement::Dir(dir.to_string()),
+                        [path, version] => {
+                            Replacement::Module(path.to_string(), version.to_string())
+                        }
+                        _ => bail!(bad()),
+                    };
+                    file.replaces.push(Replace {
+                        path: path.to_string(),
+                        version,
+                        replacement,
+                    });
+                }
+                // Other directives (`exclude`, `retract`, `godebug`, `tool`,
+                // etc.) don't affect where modules are found.
+                _ => {}
+            }
+        }
+        Ok(file)
+    }
+}
+
+/// A Go release or language version, e.g. `go1.25.5`, `go1.26rc1` or `1.26`.
+///
+/// As with the `go` command, a language version precedes the prereleases of
+/// that version, which precede its first release, e.g. `1.26` < `go1.26rc1` <
+/// `go1.26.0`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct GoVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    pub stage: GoStage,
+}
+
+/// The kind of a [`GoVersion`], in ascending order.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum GoStage {
+    /// A language version without a patch number, e.g. `1.26`.
+    Language,
+    /// A beta, e.g. `go1.26beta1`.
+    Beta(u32),
+    /// A release candidate, e.g. `go1.26rc1`.
+    Rc(u32),
+    /// A release, e.g. `go1.26.0`.
+    Release,
+}
+
+impl GoVersion {
+    /// Parse the version at the start of `s`, which may be prefixed by `go`,
+    /// ignoring anything after it, e.g. the `-wasi-on-idle-v2` of a patched
+    /// release name.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.strip_prefix("go").unwrap_or(s);
+        let mut parts = s.splitn(3, '.').map(|part| {
+            let end = part
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(part.len());
+            (part[..end].parse::<u32>().ok(), &part[end..])
+        });
+        let (major, major_rest) = parts.next()?;
+        let (minor, minor_rest) = parts.next()?;
+        let patch = if major_rest.is_empty() && minor_rest.is_empty() {
+            parts.next().and_then(|(patch, _)| patch)
+        } else {
+            None
+        };
+        let prerelease = |prefix| {
+            let number = minor_rest.strip_prefix(prefix)?;
+            let end = number
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(number.len());
+            number[..end].parse::<u32>().ok()
+        };
+        let stage = if patch.is_some() {
+            GoStage::Release
+        } else if let Some(number) = prerelease("rc") {
+            GoStage::Rc(number)
+        } else if let Some(number) = prerelease("beta") {
+            GoStage::Beta(number)
+        } else {
+            GoStage::Language
+        };
+        Some(Self {
+            major: major?,
+            minor: minor?,
+            patch: patch.unwrap_or(0),
+            stage,
+        })
+    }
+}
+
+impl fmt::Display for GoVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "go{}.{}", self.major, self.minor)?;
+        match self.stage {
+            GoStage::Language => Ok(()),
+            GoStage::Beta(number) => write!(f, "beta{number}"),
+            GoStage::Rc(number) => write!(f, "rc{number}"),
+            GoStage::Release => write!(f, ".{}", self.patch),
+        }
+    }
+}
+
+/// The `go` and `toolchain` directives which apply to the current directory.
+pub struct GoRequirement {
+    /// The `go.work` or `go.mod` file containing the directives.
+    pub file: PathBuf,
+    /// The minimum Go version required.
+    pub go: Option<GoVersion>,
+    /// The preferred toolchain, if newer than `go`.
+    pub toolchain: Option<GoVersion>,
+}
+
+/// Read the `go` and `toolchain` directives from the `go.work` file which
+/// applies to the current directory, if any, or else `./go.mod`.
+pub fn requirement() -> Result<Option<GoRequirement>> {
+    let file = match go_work_path() {
+        Some(work) => work,
+        None if Path::new("go.mod").exists() => PathBuf::from("go.mod"),
+        None => return Ok(None),
+    };
+    let contents = GoModFile::read(&file)?;
+    Ok(Some(GoRequirement {
+        go: contents.go.as_deref().and_then(GoVersion::parse),
+        toolchain: contents.toolchain.as_deref().and_then(GoVersion::parse),
+        file,
+    }))
+}
+
+/// The toolchain named by `GOTOOLCHAIN`, if it names one rather than being
+/// `local`, `auto` or `path`.
+pub fn forced_toolchain() -> Option<String> {
+    let value = go_env("GOTOOLCHAIN")?;
+    let name = value
+        .strip_suffix("+auto")
+        .or_else(|| value.strip_suffix("+path"))
+        .unwrap_or(&value);
+    (!matches!(name, "local" | "auto" | "path")).then(|| name.to_string())
+}
+
+/// Find the `go.work` file which applies to the current directory, if any,
+/// following the same rules as the `go` command.
+pub fn go_work_path() -> Option<PathBuf> {
+    match env::var("GOWORK") {
+        Ok(v) if v == "off" => None,
+        Ok(v) if !v.is_empty() => Some(PathBuf::from(v)),
+        _ => env::current_dir().ok().and_then(|dir| {
+            dir.ancestors()
+                .map(|dir| dir.join("go.work"))
+                .find(|path| path.exists())
+        }),
+    }
+}
+
+/// Look up a Go environment variable from the process environment or the file
+/// written by `go env -w`, without running `go`.
+pub fn go_env(key: &str) -> Option<String> {
+    if let Ok(value) = env::var(key) {
+        return (!value.is_empty()).then_some(value);
+    }
+    let file = match env::var("GOENV") {
+        Ok(v) if v == "off" => return None,
+        Ok(v) if !v.is_empty() => PathBuf::from(v),
+        _ => dirs::config_dir()?.join("go").join("env"),
+    };
+    fs::read_to_string(file).ok()?.lines().find_map(|line| {
+        let (k, v) = line.split_once('=')?;
+        (k.trim() == key && !v.trim().is_empty()).then(|| v.trim().to_string())
+    })
+}
+
+/// The directory in which the `go` command stores downloaded modules.
+pub fn module_cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = go_env("GOMODCACHE") {
+        return Some(PathBuf::from(dir));
+    }
+    let gopath = match go_env("GOPATH") {
+        Some(gopath) => env::split_paths(&gopath).next()?,
+        None => dirs::home_dir()?.join("go"),
+    };
+    Some(gopath.join("pkg").join("mod"))
+}
+
+/// Escape a module path or version for use in the module cache, where
+/// upper-case letters are replaced by `!` followed by the lower-case letter.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_ascii_uppercase() {
+            escaped.push('!');
+            escaped.push(c.to_ascii_lowercase());
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// Compare two Go module versions, e.g. `v1.2.3` or
+/// `v0.0.0-20240101000000-abcdef123456`.
+fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    let parse = |v: &str| semver::Version::parse(v.strip_prefix('v').unwrap_or(v)).ok();
+    match (parse(a), parse(b)) {
+        (Some(a), Some(b)) => a.cmp_precedence(&b),
+        _ => a.cmp(b),
+    }
+}
+
+/// Check that the module graph of the `go.mod` file at `path` is pruned, i.e.
+/// that its `go` directive is at least 1.17, so that its requirements include
+/// every module needed to build its packages.  Older files only list direct
+/// dependencies, whose own requirements are not read.
+fn check_pruned(path: &Path, file: &GoModFile) -> Result<()> {
+    // As with the `go` command, a missing `go` directive means Go 1.16.
+    let version = file.go.as_deref().unwrap_or("1.16");
+    match GoVersion::parse(version) {
+        Some(version) if version >= PRUNED_GO_VERSION => Ok(()),
+        _ => bail!(
+            "`{}` declares `go {version}`, so it may not list every module needed; \
+             run `go mod tidy -go=1.17` to upgrade it, or use `--discovery go-list`",
+            path.display()
+        ),
+    }
+}
+
+/// The first Go language version whose `go.mod` files list all of the
+/// modules needed to build their packages.
+const PRUNED_GO_VERSION: GoVersion = GoVersion {
+    major: 1,
+    minor: 17,
+    patch: 0,
+    stage: GoStage::Language,
+};
+
+/// Find the replacement of version `version` of the module `path` among
+/// `replaces`, preferring one for that specific version over one for all
+/// versions, as the `go` command does.
+fn find_replace<'a>(
+    replaces: &'a [(Replace, PathBuf)],
+    path: &str,
+    version: &str,
+) -> Option<&'a (Replace, PathBuf)> {
+    let matching = |wanted: Option<&str>| {
+        replaces
+            .iter()
+            .find(|(r, _)| r.path == path && r.version.as_deref() == wanted)
+    };
+    matching(Some(version)).or_else(|| matching(None))
+}
+
+/// Find the current module and its dependencies by reading `go.mod` (and
+/// `go.work`, if any) directly and looking up each required module in the
+/// module cache, without running `go`.
+///
+/// Only the requirements listed in the main modules' `go.mod` files are
+/// considered, which (since Go 1.17) include every module needed to build
+/// the main modules' packages.
+pub fn list_modules() -> Result<Vec<GoModule>> {
+    let mut main_dirs = Vec::new();
+    let mut replaces = Vec::new();
+    if let Some(work) = go_work_path() {
+        let work_dir = work.parent().unwrap_or(Path::new(".")).to_owned();
+        let file = GoModFile::read(&work)?;
+        main_dirs.extend(file.uses.iter().map(|dir| work_dir.join(dir)));
+        replaces.extend(file.replaces.into_iter().map(|r| (r, work_dir.clone())));
+    } else {
+        main_dirs.push(env::current_dir()?);
+    }
+
+    let mut modules = Vec::new();
+    let mut requires = BTreeMap::<String, String>::new();
+    let mut module_replaces = Vec::new();
+    for dir in &main_dirs {
+        let file = GoModFile::read(&dir.join("go.mod"))?;
+        check_pruned(&dir.join("go.mod"), &file)?;
+        let Some(path) = file.module else {
+            bail!(
+                "`{}` has no `module` directive",
+                dir.join("go.mod").display()
+            );
+        };
+        modules.push(GoModule {
+            path,
+            dir: dir.clone(),
+            main: true,
+            replaced: false,
+        });
+        for (path, version) in file.requires {
+            // Use the highest version required by any main module, as the
+            // `go` command would.
+            match requires.get(&path) {
+                Some(existing) if compare_versions(existing, &version).is_ge() => {}
+                _ => {
+                    requires.insert(path, version);
+                }
+            }
+        }
+        module_replaces.extend(file.replaces.into_iter().map(|r| (r, dir.clone())));
+    }
+
+    let cache = module_cache_dir();
+    let mut missing = Vec::new();
+    for (path, version) in requires {
+        if modules.iter().any(|m| m.path == path) {
+            continue;
+        }
+
+        // Replacements in `go.work` take precedence over those in `go.mod`
+        // files.
+        let replace = find_replace(&replaces, &path, &version)
+            .or_else(|| find_replace(&module_replaces, &path, &version));
+        let (dir, replaced) = match replace {
+            Some((
+                Replace {
+                    replacement: Replacement::Dir(dir),
+                    ..
+                },
+                base,
+            )) => (Some(base.join(dir)), true),
+            Some((
+                Replace {
+                    replacement: Replacement::Module(new_path, new_version),
+                    ..
+                },
+                _,
+            )) => (
+                cache.as_ref().map(|cache| {
+                    cache.join(format!("{}@{}", escape(new_path), escape(new_version)))
+                }),
+                true,
+            ),
+            None => (
+                cache
+                    .as_ref()
+                    .map(|cache| cache.join(format!("{}@{}", escape(&path), escape(&version)))),
+                false,
+            ),
+        };
+
+        match dir {
+            Some(dir) if dir.is_dir() => modules.push(GoModule {
+                path,
+                dir,
+                main: false,
+                replaced,
+            }),
+            _ => missing.push(format!("{path}@{version}")),
+        }
+    }
+
+    if !missing.is_empty() {
+        bail!(
+            "the following modules were not found in the module cache ({}):\n  {}\n\
+             Run `go mod download` to download them, or use `--discovery go-list`.",
+            cache
+                .map(|cache| cache.display().to_string())
+                .unwrap_or_else(|| "unknown location".into()),
+            missing.join("\n  ")
+        );
+    }
+
+    Ok(modules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_go_mod() {
+        let file = GoModFile::parse(
+            r#"
+module example.com/app // the main module
+
+go 1.25.0
+
+toolchain go1.25.5
+
+require github.com/Foo/bar v1.2.3
+
+require (
+	go.bytecodealliance.org/pkg v0.2.0
+	golang.org/x/sys v0.30.0 // indirect
+)
+
+replace github.com/Foo/bar => ../bar
+
+replace (
+	golang.org/x/sys v0.30.0 => golang.org/x/sys v0.31.0
+)
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(file.module.as_deref(), Some("example.com/app"));
+        assert_eq!(file.go.as_deref(), Some("1.25.0"));
+        assert_eq!(file.toolchain.as_deref(), Some("go1.25.5"));
+        assert_eq!(
+            file.requires,
+            [
+                ("github.com/Foo/bar", "v1.2.3"),
+                ("go.bytecodealliance.org/pkg", "v0.2.0"),
+                ("golang.org/x/sys", "v0.30.0"),
+            ]
+            .map(|(p, v)| (p.to_string(), v.to_string()))
+        );
+        assert_eq!(
+            file.replaces,
+            [
+                Replace {
+                    path: "github.com/Foo/bar".into(),
+                    version: None,
+                    replacement: Replacement::Dir("../bar".into()),
+                },
+                Replace {
+                    path: "golang.org/x/sys".into(),
+                    version: Some("v0.30.0".into()),
+                    replacement: Replacement::Module("golang.org/x/sys".into(), "v0.31.0".into()),
+                },
+            ]
+        );
+        assert_eq!(escape("github.com/Foo/bar"), "github.com/!foo/bar");
+    }
+
+    #[test]
+    fn test_find_replace() {
+        let file = GoModFile::parse(
+            "module example.com/app\n\
+             replace example.com/dep => ../dep\n\
+             replace example.com/dep v1.0.0 => ../dep-v1\n",
+        )
+        .unwrap();
+        let replaces = file
+            .replaces
+            .into_iter()
+            .map(|r| (r, PathBuf::new()))
+            .collect::<Vec<_>>();
+        let dir = |version| match find_replace(&replaces, "example.com/dep", version) {
+            Some((
+                Replace {
+                    replacement: Replacement::Dir(dir),
+                    ..
+                },
+                _,
+            )) => Some(dir.as_str()),
+            _ => None,
+        };
+        assert_eq!(dir("v1.0.0"), Some("../dep-v1"));
+        assert_eq!(dir("v1.1.0"), Some("../dep"));
+        assert!(find_replace(&replaces, "example.com/other", "v1.0.0").is_none());
+    }
+
+    #[test]
+    fn test_check_pruned() {
+        let check = |contents: &str| {
+            check_pruned(Path::new("go.mod"), &GoModFile::parse(contents).unwrap())
+        };
+        assert!(check("module m\ngo 1.17\n").is_ok());
+        assert!(check("module m\ngo 1.25.5\n").is_ok());
+        let error = check("module m\ngo 1.16\n").unwrap_err().to_string();
+        assert!(error.contains("`go 1.16`"), "{error}");
+        assert!(error.contains("--discovery go-list"), "{error}");
+        assert!(check("module m\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_go_version() {
+        let version = |major, minor, patch, stage| {
+            Some(GoVersion {
+                major,
+                minor,
+                patch,
+                stage,
+            })
+        };
+        assert_eq!(
+            GoVersion::parse("go1.25.5"),
+            version(1, 25, 5, GoStage::Release)
+        );
+        assert_eq!(
+            GoVersion::parse("1.26"),
+            version(1, 26, 0, GoStage::Language)
+        );
+        assert_eq!(
+            GoVersion::parse("go1.26rc1"),
+            version(1, 26, 0, GoStage::Rc(1))
+        );
+        assert_eq!(
+            GoVersion::parse("go1.26beta2"),
+            version(1, 26, 0, GoStage::Beta(2))
+        );
+        assert_eq!(
+            GoVersion::parse("go1.25.5-wasi-on-idle-v2"),
+            version(1, 25, 5, GoStage::Release)
+        );
+        assert_eq!(
+            GoVersion::parse("go1.25.5 X:nodwarf5"),
+            version(1, 25, 5, GoStage::Release)
+        );
+        assert_eq!(GoVersion::parse("default"), None);
+        assert!(GoVersion::parse("go1.9.2") < GoVersion::parse("go1.25"));
+
+        // Prereleases sort between the language version and the release.
+        let order = ["1.26", "go1.26beta1", "go1.26rc1", "go1.26rc2", "go1.26.0"]
+            .map(|version| GoVersion::parse(version).unwrap());
+        assert!(order.is_sorted());
+        assert!(!order.windows(2).any(|pair| pair[0] == pair[1]));
+        assert_eq!(order[2].to_string(), "go1.26rc1");
+        assert_eq!(order[0].to_string(), "go1.26");
+    }
+}
@@ -1,5 +1,19 @@
 pub mod cmd_bindings;
 pub mod cmd_build;
+pub mod cmd_compose;
+pub mod cmd_doctor;
 pub mod cmd_test;
+pub mod cmd_toolchain;
 pub mod command;
+pub mod config;
+pub mod features;
+pub mod gomod;
+pub mod packages;
+pub mod preflight;
+pub mod stubs;
+pub mod targets;
+#[cfg(test)]
+mod testing;
+pub mod toolchain;
 pub mod utils;
+pub mod virt;
@@ -0,0 +1,603 @@
+use crate::config::{Origin, PackageVersion, WitConfig, WitSource};
+use anyhow::{Context, Result, bail};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, mem,
+    path::{Path, PathBuf},
+};
+use wit_parser::{
+    PackageId, PackageName, ParsedUsePath, Resolve, UnresolvedPackage, UnresolvedPackageGroup,
+    parse_use_path,
+};
+
+/// A WIT package parsed from one of the WIT sources, prior to resolution.
+struct Candidate {
+    /// A group containing only this package; nested packages are split out
+    /// into candidates of their own.
+    group: UnresolvedPackageGroup,
+    /// The directory or file the package was parsed from.
+    location: PathBuf,
+    /// Index of the source the package was found in.
+    source: usize,
+}
+
+/// The main package of a WIT source.
+enum MainPackage {
+    /// A WIT package which will be resolved along with the others.
+    Unresolved(PackageName),
+    /// A wasm-encoded WIT package, which was resolved as soon as it was read.
+    Resolved(PackageId),
+}
+
+/// The result of [`load_packages`].
+pub struct LoadedPackages {
+    /// The main package of each source, in the same order as the sources.
+    pub mains: Vec<PackageId>,
+    /// Packages which were replaced by a newer, semver-compatible version.
+    pub replaced: BTreeMap<PackageName, PackageName>,
+}
+
+impl LoadedPackages {
+    /// Update a world name such as `wasi:http/proxy@0.2.0` to refer to the
+    /// version of its package which was actually loaded.
+    pub fn world_name(&self, world: &str) -> String {
+        match parse_use_path(world) {
+            Ok(ParsedUsePath::Package(package, name)) => match self.replaced.get(&package) {
+                Some(PackageName {
+                    namespace,
+                    name: package,
+                    version: Some(version),
+                }) => format!("{namespace}:{package}/{name}@{version}"),
+                _ => world.to_string(),
+            },
+            _ => world.to_string(),
+        }
+    }
+}
+
+/// Load the packages found in the sources of `config` into `resolve`.
+///
+/// Unlike loading each source with `Resolve::push_path`, a package which is
+/// defined by more than one source is only loaded once:
+///
+/// * When several semver-compatible versions of a package are found, only the
+///   highest is used (much like Go's minimal version selection), and any
+///   references to the others are redirected to it, unless a different
+///   version was requested or pinned explicitly.
+/// * When the same version is found more than once, the copy in the pinned
+///   path is used if the package is pinned.  Otherwise, the first copy is used
+///   and any others are checked for consistency with it.
+pub fn load_packages(resolve: &mut Resolve, config: &WitConfig) -> Result<LoadedPackages> {
+    let WitConfig {
+        sources,
+        pins,
+        versions,
+        ..
+    } = config;
+
+    // Pinned paths are loaded even if they aren't otherwise referenced.
+    let mut all_sources = sources.to_vec();
+    for pin in pins {
+        if !all_sources.iter().any(|source| source.path == pin.path) {
+            all_sources.push(WitSource {
+                path: pin.path.clone(),
+                origin: pin.origin.clone(),
+            });
+        }
+    }
+
+    let mut candidates = Vec::new();
+    let mut mains = Vec::new();
+    for (index, source) in all_sources.iter().enumerate() {
+        mains.push(read_source(resolve, &source.path, index, &mut candidates)?);
+    }
+
+    // A pinned package is used in place of other semver-compatible versions,
+    // as if its version had been requested.
+    let mut versions = versions.to_vec();
+    for pin in pins {
+        for candidate in &candidates {
+            let name = &candidate.group.main.name;
+            if name.version.is_some()
+                && pin_matches(&pin.package, name)
+                && is_at(candidate, &pin.path, &all_sources)
+                && !versions.iter().any(|v| v.package == name.to_string())
+            {
+                versions.push(PackageVersion {
+                    package: name.to_string(),
+                    origin: pin.origin.clone(),
+                });
+            }
+        }
+    }
+
+    let replaced = select_versions(
+        candidates
+            .iter()
+            .map(|c| &c.group.main.name)
+            .chain(resolve.package_names.keys()),
+        &versions,
+    )?;
+    for (old, new) in &replaced {
+        let users = candidates
+            .iter()
+            .filter(|c| &c.group.main.name == old)
+            .map(|c| describe(c, &all_sources))
+            .collect::<Vec<_>>();
+        eprintln!(
+            "Note: using `{new}` in place of `{old}` (defined in {}).",
+            users.join(", ")
+        );
+    }
+    candidates.retain(|c| !replaced.contains_key(&c.group.main.name));
+    for candidate in &mut candidates {
+        redirect_deps(&mut candidate.group.main, &replaced)?;
+    }
+
+    let mut by_name = BTreeMap::<PackageName, Vec<usize>>::new();
+    for (index, candidate) in candidates.iter().enumerate() {
+        by_name
+            .entry(candidate.group.main.name.clone())
+            .or_default()
+            .push(index);
+    }
+
+    let mut used_pins = BTreeSet::new();
+    let mut winners = BTreeMap::new();
+    let mut unchecked = Vec::new();
+    for (name, indexes) in &by_name {
+        // Wasm-encoded packages were resolved as soon as they were read, so
+        // they take precedence over any WIT copies.
+        if resolve.package_names.contains_key(name) {
+            unchecked.extend(indexes.iter().copied());
+            continue;
+        }
+
+        let pinned = pins.iter().enumerate().find_map(|(i, pin)| {
+            if !pin_matches(&pin.package, name) {
+                return None;
+            }
+            indexes
+                .iter()
+                .copied()
+                .find(|&c| is_at(&candidates[c], &pin.path, &all_sources))
+                .map(|c| (i, c))
+        });
+
+        match pinned {
+            Some((pin, winner)) => {
+                used_pins.insert(pin);
+                winners.insert(name.clone(), winner);
+            }
+            None => {
+                winners.insert(name.clone(), indexes[0]);
+                unchecked.extend(indexes[1..].iter().copied());
+            }
+        }
+    }
+
+    for (i, pin) in pins.iter().enumerate() {
+        if !used_pins.contains(&i) {
+            bail!(
+                "package `{}` was pinned to `{}` by {}, but no such package is defined there",
+                pin.package,
+                pin.path.display(),
+                pin.origin
+            );
+        }
+    }
+
+    let mut groups = winners
+        .values()
+        .map(|&c| candidates[c].group.clone())
+        .collect::<Vec<_>>();
+    if !groups.is_empty() {
+        let main = groups.remove(0);
+        resolve.push_groups(main, groups).map_err(|e| {
+            let e = anyhow::Error::from(e);
+            if replaced.is_empty() {
+                e
+            } else {
+                e.context(format!(
+                    "failed to resolve WIT packages after replacing {}; use \
+                     `--package-version` or `package_versions` in the main module's \
+                     `componentize-go.toml` to choose a different version",
+                    replaced
+                        .iter()
+                        .map(|(old, new)| format!("`{old}` with `{new}`"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            }
+        })?;
+    }
+
+    // Check that every copy of a package which was not used agrees with the
+    // one which was, unless the user explicitly chose one via a pin.
+    for &loser in &unchecked {
+        let name = &candidates[loser].group.main.name;
+        if pins.iter().any(|pin| pin_matches(&pin.package, name)) {
+            continue;
+        }
+        let Some(tmp) = resolve_alone(resolve, &candidates, &winners, loser) else {
+            continue;
+        };
+        if let Err(e) = resolve.merge(tmp) {
+            let copy = &candidates[loser];
+            let used = match winners.get(name) {
+                Some(&winner) => describe(&candidates[winner], &all_sources),
+                None => "a wasm-encoded package".to_string(),
+            };
+            return Err(e.context(format!(
+                "package `{name}` is defined differently in {} and {used}; use \
+                 `--pin-package` or the `packages` table in the main module's \
+                 `componentize-go.toml` to choose one",
+                describe(copy, &all_sources),
+            )));
+        }
+    }
+
+    let mains = mains[..sources.len()]
+        .iter()
+        .map(|main| match main {
+            MainPackage::Unresolved(name) => {
+                resolve.package_names[replaced.get(name).unwrap_or(name)]
+            }
+            &MainPackage::Resolved(id) => id,
+        })
+        .collect();
+
+    Ok(LoadedPackages { mains, replaced })
+}
+
+/// Choose which version of each package to use, returning a map from each
+/// version which should not be used to the one which should.
+///
+/// Among semver-compatible versions of a package, the highest is chosen unless
+/// another one is named in `versions`.
+fn select_versions<'a>(
+    names: impl Iterator<Item = &'a PackageName>,
+    versions: &[PackageVersion],
+) -> Result<BTreeMap<PackageName, PackageName>> {
+    let mut tracks = BTreeMap::<_, BTreeSet<&PackageName>>::new();
+    for name in names {
+        if let Some(version) = &name.version {
+            tracks
+                .entry((
+                    &name.namespace,
+                    &name.name,
+                    PackageName::version_compat_track(version),
+                ))
+                .or_default()
+                .insert(name);
+        }
+    }
+
+    for requested in versions {
+        if !tracks
+            .values()
+            .flatten()
+            .any(|name| name.to_string() == requested.package)
+        {
+            bail!(
+                "version `{}` was requested by {}, but no such package was found",
+                requested.package,
+                requested.origin
+            );
+        }
+    }
+
+    let mut replaced = BTreeMap::new();
+    for names in tracks.values() {
+        let requested = names
+            .iter()
+            .filter_map(|name| {
+                versions
+                    .iter()
+                    .find(|v| v.package == name.to_string())
+                    .map(|v| (*name, &v.origin))
+            })
+            .collect::<Vec<_>>();
+        if let [(first, first_origin), (second, second_origin), ..] = requested[..] {
+            bail!(
+                "`{first}` was requested by {first_origin} and `{second}` by {second_origin},                  but only one of these semver-compatible versions can be used"
+            );
+        }
+        let chosen = requested
+            .first()
+            .map(|&(name, _)| name)
+            // `PackageName`s with the same namespace and name are ordered by
+            // version.
+            .or_else(|| names.last().copied());
+        if let Some(chosen) = chosen {
+            for &name in names {
+                if name != chosen {
+                    replaced.insert(name.clone(), chosen.clone());
+                }
+            }
+        }
+    }
+    Ok(replaced)
+}
+
+/// Update the dependencies of `package` to refer to the chosen versions of any
+/// packages which were replaced.
+fn redirect_deps(
+    package: &mut UnresolvedPackage,
+    replaced: &BTreeMap<PackageName, PackageName>,
+) -> Result<()> {
+    if !package
+        .foreign_deps
+        .keys()
+        .any(|name| replaced.contains_key(name))
+    {
+        return Ok(());
+    }
+
+    for (name, items) in mem::take(&mut package.foreign_deps) {
+        let name = replaced.get(&name).cloned().unwrap_or(name);
+        match package.foreign_deps.get_mut(&name) {
+            Some(existing) => {
+                for (item, value) in items {
+                    if existing.insert(item.clone(), value).is_some() {
+                        bail!(
+                            "package `{}` refers to `{item}` in more than one version of \
+                             `{name}`, so those versions cannot be unified",
+                            package.name
+                        );
+                    }
+                }
+            }
+            None => {
+                package.foreign_deps.insert(name, items);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parse the WIT directory or file at `path`, adding its packages to
+/// `candidates`.
+///
+/// As with `Resolve::push_path`, a directory may contain a `deps` directory
+/// with additional packages.  Wasm-encoded packages are pushed directly into
+/// `resolve`.
+fn read_source(
+    resolve: &mut Resolve,
+    path: &Path,
+    source: usize,
+    candidates: &mut Vec<Candidate>,
+) -> Result<MainPackage> {
+    let mut add = |group: UnresolvedPackageGroup, location: &Path| {
+        let UnresolvedPackageGroup {
+            main,
+            nested,
+            source_map,
+        } = group;
+        let name = main.name.clone();
+        for package in nested.into_iter().chain([main]) {
+            candidates.push(Candidate {
+                group: UnresolvedPackageGroup {
+                    main: package,
+                    nested: Vec::new(),
+                    source_map: source_map.clone(),
+                },
+                location: location.to_owned(),
+                source,
+            });
+        }
+        name
+    };
+
+    if !path.is_dir() {
+        return Ok(match read_file(path)? {
+            Some(group) => MainPackage::Unresolved(add(group, path)),
+            None => MainPackage::Resolved(resolve.push_file(path)?),
+        });
+    }
+
+    let deps = path.join("deps");
+    if deps.is_dir() {
+        let mut entries = fs::read_dir(&deps)
+            .and_then(|entries| entries.collect::<std::io::Result<Vec<_>>>())
+            .with_context(|| format!("failed to read directory `{}`", deps.display()))?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let dep = entry.path();
+            if dep.is_dir() {
+                let group = UnresolvedPackageGroup::parse_dir(&dep)
+                    .with_context(|| format!("failed to parse package: {}", dep.display()))?;
+                add(group, &dep);
+            } else if matches!(
+                dep.extension().and_then(|s| s.to_str()),
+                Some("wit" | "wat" | "wasm")
+            ) {
+                match read_file(&dep)? {
+                    Some(group) => {
+                        add(group, &dep);
+                    }
+                    None => {
+                        resolve.push_file(&dep)?;
+                    }
+                }
+            }
+        }
+    }
+
+    let group = UnresolvedPackageGroup::parse_dir(path)
+        .with_context(|| format!("failed to parse package: {}", path.display()))?;
+    Ok(MainPackage::Unresolved(add(group, path)))
+}
+
+/// Parse the WIT file at `path`, or return `None` if it is a wasm-encoded
+/// package which must be loaded with `Resolve::push_file` instead.
+fn read_file(path: &Path) -> Result<Option<UnresolvedPackageGroup>> {
+    if matches!(
+        path.extension().and_then(|s| s.to_str()),
+        Some("wasm" | "wat")
+    ) {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("failed to read WIT file `{}`", path.display()))?;
+    UnresolvedPackageGroup::parse(path, &contents).map(Some)
+}
+
+/// Resolve the candidate at `index` on its own (along with the chosen copies
+/// of its dependencies) so that it may be merged with the chosen copy.
+///
+/// Returns `None` if that's not possible, e.g. because it depends on
+/// wasm-encoded packages.
+fn resolve_alone(
+    resolve: &Resolve,
+    candidates: &[Candidate],
+    winners: &BTreeMap<PackageName, usize>,
+    index: usize,
+) -> Option<Resolve> {
+    let mut deps = BTreeSet::new();
+    let mut queue = vec![index];
+    while let Some(c) = queue.pop() {
+        for name in candidates[c].group.main.foreign_deps.keys() {
+            let &winner = winners.get(name)?;
+            if deps.insert(winner) {
+                queue.push(winner);
+            }
+        }
+    }
+
+    let mut tmp = Resolve {
+        all_features: resolve.all_features,
+        features: resolve.features.clone(),
+        ..Default::default()
+    };
+    tmp.push_groups(
+        candidates[index].group.clone(),
+        deps.into_iter()
+            .map(|c| candidates[c].group.clone())
+            .collect(),
+    )
+    .ok()?;
+    Some(tmp)
+}
+
+/// Whether a pin for `package`, which may omit the version, applies to `name`.
+fn pin_matches(package: &str, name: &PackageName) -> bool {
+    package == name.to_string() || package == format!("{}:{}", name.namespace, name.name)
+}
+
+/// Whether `candidate` was found at `path`, or in a source at `path`.
+fn is_at(candidate: &Candidate, path: &Path, sources: &[WitSource]) -> bool {
+    let path = canonical(path);
+    canonical(&candidate.location) == path || canonical(&sources[candidate.source].path) == path
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_owned())
+}
+
+fn describe(candidate: &Candidate, sources: &[WitSource]) -> String {
+    let origin = &sources[candidate.source].origin;
+    match origin {
+        Origin::CommandLine => format!("`{}`", candidate.location.display()),
+        _ => format!("`{}` (from {origin})", candidate.location.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PackagePin;
+
+    fn name(s: &str) -> PackageName {
+        let (package, version) = s.split_once('@').unwrap();
+        let (namespace, name) = package.split_once(':').unwrap();
+        PackageName {
+            namespace: namespace.into(),
+            name: name.into(),
+            version: Some(version.parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_select_versions() {
+        let names = [
+            "wasi:io@0.2.0",
+            "wasi:io@0.2.3",
+            "wasi:io@0.3.0",
+            "wasi:cli@1.0.0",
+            "wasi:cli@1.2.0",
+            "wasi:cli@2.0.0",
+        ]
+        .map(name);
+
+        let replaced = select_versions(names.iter(), &[]).unwrap();
+        let expected = [
+            ("wasi:io@0.2.0", "wasi:io@0.2.3"),
+            ("wasi:cli@1.0.0", "wasi:cli@1.2.0"),
+        ]
+        .map(|(old, new)| (name(old), name(new)))
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+        assert_eq!(replaced, expected);
+
+        let requested = [PackageVersion {
+            package: "wasi:io@0.2.0".into(),
+            origin: Origin::CommandLine,
+        }];
+        let replaced = select_versions(names.iter(), &requested).unwrap();
+        assert_eq!(replaced[&name("wasi:io@0.2.3")], name("wasi:io@0.2.0"));
+        assert!(!replaced.contains_key(&name("wasi:io@0.2.0")));
+
+        let requested = [PackageVersion {
+            package: "wasi:io@0.2.9".into(),
+            origin: Origin::CommandLine,
+        }];
+        assert!(select_versions(names.iter(), &requested).is_err());
+
+        // Two versions of the same track cannot both be requested.
+        let requested = ["wasi:io@0.2.0", "wasi:io@0.2.3"].map(|package| PackageVersion {
+            package: package.into(),
+            origin: Origin::CommandLine,
+        });
+        assert!(select_versions(names.iter(), &requested).is_err());
+    }
+
+    #[test]
+    fn test_pin_older_version() {
+        let dir = crate::testing::temp_path("pins");
+        for version in ["0.2.0", "0.2.3"] {
+            fs::create_dir_all(dir.join(version)).unwrap();
+            fs::write(
+                dir.join(version).join("io.wit"),
+                format!("package wasi:io@{version};\n\ninterface streams {{}}\n"),
+            )
+            .unwrap();
+        }
+        let source = |version: &str| WitSource {
+            path: dir.join(version),
+            origin: Origin::CommandLine,
+        };
+        let config = WitConfig {
+            sources: vec![source("0.2.3"), source("0.2.0")],
+            pins: vec![PackagePin {
+                package: "wasi:io@0.2.0".into(),
+                path: dir.join("0.2.0"),
+                origin: Origin::CommandLine,
+            }],
+            ..WitConfig::default()
+        };
+
+        let mut resolve = Resolve::default();
+        let loaded = load_packages(&mut resolve, &config);
+        fs::remove_dir_all(&dir).unwrap();
+
+        // The pinned version wins, although a higher one was found.
+        let loaded = loaded.unwrap();
+        assert_eq!(
+            loaded.replaced[&name("wasi:io@0.2.3")],
+            name("wasi:io@0.2.0")
+        );
+        assert!(resolve.package_names.contains_key(&name("wasi:io@0.2.0")));
+        assert!(!resolve.package_names.contains_key(&name("wasi:io@0.2.3")));
+    }
+}
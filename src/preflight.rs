@@ -0,0 +1,357 @@
+use crate::utils::world_name;
+use anyhow::{Result, bail};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+use wasmparser::{ExternalKind, FuncType, Parser, Payload, TypeRef};
+use wit_parser::{
+    Function, LiftLowerAbi, ManglingAndAbi, Resolve, WasmExport, WasmExportKind, WorldId,
+    WorldItem, WorldKey, abi::WasmType,
+};
+
+/// Check that the exports and imports of the core module in `wasm_file` match
+/// what `world` requires, before it is encoded as a component.
+///
+/// `ComponentEncoder` reports these problems in terms of core wasm names, so
+/// this reports each in terms of the WIT item concerned and the generated Go
+/// package which should provide it, with hints for common mistakes such as a
+/// missing import of the generated `wit_exports` package.
+pub fn check_module_exports(wasm_file: &Path, resolve: &Resolve, world: WorldId) -> Result<()> {
+    let wasm = fs::read(wasm_file)?;
+    let module = ModuleInfo::parse(&wasm)?;
+    let target = world_name(resolve, world);
+
+    let mut violations = Vec::new();
+    let mut hints = Vec::new();
+
+    let mut expected = 0;
+    let mut missing = 0;
+    for (key, item) in &resolve.worlds[world].exports {
+        let (interface, functions) = match item {
+            WorldItem::Function(function) => (None, vec![function]),
+            WorldItem::Interface { id, .. } => (
+                Some(key),
+                resolve.interfaces[*id].functions.values().collect(),
+            ),
+            WorldItem::Type { .. } => continue,
+        };
+        for function in functions {
+            expected += 1;
+            let item = match interface {
+                Some(key) => format!("{}#{}", resolve.name_world_key(key), function.name),
+                None => function.name.clone(),
+            };
+            let Some((name, abi)) = export_names(resolve, interface, function)
+                .into_iter()
+                .find(|(name, _)| module.exports.contains_key(name))
+            else {
+                missing += 1;
+                violations.push(format!(
+                    "does not export `{item}`, which should be provided by `{}` in Go package `{}`",
+                    upper_camel_case(function.item_name()),
+                    go_package(resolve, interface)
+                ));
+                if let Some(other) = module
+                    .exports
+                    .keys()
+                    .filter(|other| !other.starts_with("[callback]"))
+                    .find(|other| unversioned(other) == unversioned(&item))
+                {
+                    hints.push(format!(
+                        "`{other}` is exported instead of `{item}`; the bindings may have been \
+                         generated from a different version of the WIT, so regenerate them with \
+                         `componentize-go bindings`"
+                    ));
+                }
+                continue;
+            };
+
+            let signature = resolve.wasm_signature(abi.export_variant(), function);
+            let params = signature.params.iter().map(core_type).collect::<Vec<_>>();
+            let results = signature.results.iter().map(core_type).collect::<Vec<_>>();
+            let actual = &module.exports[&name];
+            if actual.params() != params || actual.results() != results {
+                violations.push(format!(
+                    "exports `{item}` as `{name}` with type {}, but the WIT requires {}",
+                    signature_string(actual.params(), actual.results()),
+                    signature_string(&params, &results)
+                ));
+                hints.push(
+                    "the bindings are out of date with the WIT; regenerate them with \
+                     `componentize-go bindings`"
+                        .to_string(),
+                );
+            }
+        }
+    }
+    if expected > 0 && missing == expected {
+        hints.push(format!(
+            "none of the exports of `{target}` are present; if the bindings were generated with \
+             `--pkg-name`, make sure the program imports the generated `wit_exports` package, \
+             e.g. `import _ \"<pkg-name>/wit_exports\"`"
+        ));
+    }
+
+    for (module_name, name) in &module.imports {
+        if module_name == "wasi_snapshot_preview1" {
+            continue;
+        }
+        let (key, items) = match module_name.strip_prefix("[export]") {
+            Some(key) => (key, &resolve.worlds[world].exports),
+            None => (module_name.as_str(), &resolve.worlds[world].imports),
+        };
+        if key == "$root" {
+            continue;
+        }
+        let item = items
+            .iter()
+            .find(|(item_key, _)| resolve.name_world_key(item_key) == key);
+        match item {
+            Some((_, WorldItem::Interface { id, .. })) => {
+                // Intrinsics such as `[resource-drop]foo` are left to the
+                // encoder.
+                if !name.starts_with('[')
+                    && !resolve.interfaces[*id]
+                        .functions
+                        .contains_key(name.as_str())
+                {
+                    violations.push(format!(
+                        "imports `{key}#{name}`, which is not a function of `{key}`"
+                    ));
+                    hints.push(
+                        "the bindings are out of date with the WIT; regenerate them with \
+                         `componentize-go bindings`"
+                            .to_string(),
+                    );
+                }
+            }
+            Some(_) => {}
+            None => {
+                violations.push(format!(
+                    "imports `{module_name}#{name}`, but `{target}` has no such {}",
+                    if module_name.starts_with("[export]") {
+                        "export"
+                    } else {
+                        "import"
+                    }
+                ));
+                hints.push(format!(
+                    "the bindings may have been generated for a world other than `{target}`; \
+                     regenerate them, or select the right world with `--world`"
+                ));
+            }
+        }
+    }
+
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let mut seen = BTreeSet::new();
+    hints.retain(|hint| seen.insert(hint.clone()));
+    bail!(
+        "`{}` does not match `{target}`:{}{}",
+        wasm_file.display(),
+        violations
+            .iter()
+            .map(|violation| format!("\n  - {violation}"))
+            .collect::<String>(),
+        hints
+            .iter()
+            .map(|hint| format!("\nhint: {hint}"))
+            .collect::<String>()
+    )
+}
+
+/// The core names under which `function` may be exported, depending on
+/// whether the bindings lift it synchronously or asynchronously.
+fn export_names(
+    resolve: &Resolve,
+    interface: Option<&WorldKey>,
+    function: &Function,
+) -> Vec<(String, ManglingAndAbi)> {
+    [
+        LiftLowerAbi::Sync,
+        LiftLowerAbi::AsyncCallback,
+        LiftLowerAbi::AsyncStackful,
+    ]
+    .into_iter()
+    .map(|abi| {
+        let abi = ManglingAndAbi::Legacy(abi);
+        let name = resolve.wasm_export_name(
+            abi,
+            WasmExport::Func {
+                interface,
+                func: function,
+                kind: WasmExportKind::Normal,
+            },
+        );
+        (name, abi)
+    })
+    .collect()
+}
+
+/// The name of the Go package which the bindings generated by
+/// `componentize-go bindings` expect to provide the exports of `interface`,
+/// or of the world itself if `None`.
+fn go_package(resolve: &Resolve, interface: Option<&WorldKey>) -> String {
+    let name = match interface {
+        Some(WorldKey::Name(name)) => snake_case(name),
+        Some(WorldKey::Interface(id)) => {
+            let interface = &resolve.interfaces[*id];
+            let Some(package) = interface.package.map(|id| &resolve.packages[id].name) else {
+                return "export_wit_world".to_string();
+            };
+            // As with the bindings, the version is only included when several
+            // versions of the package are present.
+            let version = match &package.version {
+                Some(version)
+                    if resolve.packages.iter().any(|(_, other)| {
+                        other.name.namespace == package.namespace
+                            && other.name.name == package.name
+                            && other.name.version != package.version
+                    }) =>
+                {
+                    format!("{}_", version.to_string().replace(['.', '-', '+'], "_"))
+                }
+                _ => String::new(),
+            };
+            format!(
+                "{}_{}_{version}{}",
+                snake_case(&package.namespace),
+                snake_case(&package.name),
+                snake_case(interface.name.as_deref().unwrap_or_default())
+            )
+        }
+        None => "wit_world".to_string(),
+    };
+    format!("export_{name}")
+}
+
+fn snake_case(name: &str) -> String {
+    name.to_ascii_lowercase().replace('-', "_")
+}
+
+fn upper_camel_case(name: &str) -> String {
+    name.split(['-', '.'])
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| {
+                    first.to_ascii_uppercase().to_string() + &chars.as_str().to_ascii_lowercase()
+                })
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// `name` without the version of its package, if any, e.g. `wasi:cli/run#run`
+/// for `wasi:cli/run@0.2.0#run`.
+fn unversioned(name: &str) -> String {
+    let name = name.rsplit(']').next().unwrap_or(name);
+    match (name.split_once('@'), name.split_once('#')) {
+        (Some((package, _)), Some((_, function))) => format!("{package}#{function}"),
+        _ => name.to_string(),
+    }
+}
+
+fn core_type(ty: &WasmType) -> wasmparser::ValType {
+    match ty {
+        WasmType::I32 | WasmType::Pointer | WasmType::Length => wasmparser::ValType::I32,
+        WasmType::I64 | WasmType::PointerOrI64 => wasmparser::ValType::I64,
+        WasmType::F32 => wasmparser::ValType::F32,
+        WasmType::F64 => wasmparser::ValType::F64,
+    }
+}
+
+fn signature_string(params: &[wasmparser::ValType], results: &[wasmparser::ValType]) -> String {
+    let list = |types: &[wasmparser::ValType]| {
+        types
+            .iter()
+            .map(|ty| ty.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    format!("`({}) -> ({})`", list(params), list(results))
+}
+
+/// The functions a core module imports and exports.
+struct ModuleInfo {
+    imports: Vec<(String, String)>,
+    exports: BTreeMap<String, FuncType>,
+}
+
+impl ModuleInfo {
+    fn parse(wasm: &[u8]) -> Result<Self> {
+        let mut types = Vec::new();
+        let mut functions = Vec::new();
+        let mut imports = Vec::new();
+        let mut exports = Vec::new();
+        for payload in Parser::new(0).parse_all(wasm) {
+            match payload? {
+                Payload::TypeSection(reader) => {
+                    for group in reader {
+                        for ty in group?.into_types() {
+                            types.push(match ty.composite_type.inner {
+                                wasmparser::CompositeInnerType::Func(ty) => Some(ty),
+                                _ => None,
+                            });
+                        }
+                    }
+                }
+                Payload::ImportSection(reader) => {
+                    for import in reader.into_imports() {
+                        let import = import?;
+                        if let TypeRef::Func(ty) | TypeRef::FuncExact(ty) = import.ty {
+                            imports.push((import.module.to_string(), import.name.to_string()));
+                            functions.push(ty);
+                        }
+                    }
+                }
+                Payload::FunctionSection(reader) => {
+                    for ty in reader {
+                        functions.push(ty?);
+                    }
+                }
+                Payload::ExportSection(reader) => {
+                    for export in reader {
+                        let export = export?;
+                        if export.kind == ExternalKind::Func {
+                            exports.push((export.name.to_string(), export.index));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        let exports = exports
+            .into_iter()
+            .filter_map(|(name, function)| {
+                let ty = types
+                    .get(*functions.get(function as usize)? as usize)?
+                    .clone()?;
+                Some((name, ty))
+            })
+            .collect();
+        Ok(Self { imports, exports })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        assert_eq!(unversioned("wasi:cli/run@0.2.0#run"), "wasi:cli/run#run");
+        assert_eq!(
+            unversioned("[async-lift]wasi:cli/run@0.3.0-rc-2026-03-15#run"),
+            "wasi:cli/run#run"
+        );
+        assert_eq!(unversioned("run"), "run");
+        assert_eq!(upper_camel_case("get-HTTP-body"), "GetHttpBody");
+        assert_eq!(upper_camel_case("handle"), "Handle");
+    }
+}
@@ -0,0 +1,559 @@
+use crate::{cmd_compose::compose_plugs, targets::decode};
+use anyhow::{Context, Result};
+use std::{collections::BTreeSet, fs, path::Path};
+use wasm_encoder::{
+    CodeSection, ConstExpr, DataSection, ExportKind, ExportSection, Function, FunctionSection,
+    MemorySection, MemoryType, Module, TypeSection, ValType,
+};
+use wasmparser::{
+    ElementItems, ElementKind, ExternalKind, FunctionBody, Operator, Parser, Payload, TypeRef,
+};
+use wit_component::{ComponentEncoder, StringEncoding};
+use wit_parser::{
+    Resolve, WorldItem, WorldKey,
+    abi::{AbiVariant, WasmType},
+};
+
+/// The module from which a core module imports the functions of the WASI
+/// Preview 1 adapter.
+const PREVIEW1: &str = "wasi_snapshot_preview1";
+
+/// Satisfy each interface which the component in `component` imports, but
+/// can never call, with a composed component whose functions trap, returning
+/// the names of the interfaces stubbed, e.g. `wasi:cli/environment@0.2.6`.
+///
+/// Such imports typically come from bindings for parts of the target world
+/// which the Go code does not use, or from parts of the WASI Preview 1
+/// adapter which it never calls.  An interface is kept if any of its
+/// functions is reachable from the main module's exports, directly or through
+/// the adapter, or if a kept interface uses its types.
+pub fn stub_unused_imports(component: &Path) -> Result<Vec<String>> {
+    let bytes = fs::read(component)?;
+    let modules = core_modules(&bytes)?;
+    let Some((main, others)) = modules.split_first() else {
+        return Ok(Vec::new());
+    };
+
+    // The names of the modules, i.e. interfaces, from which a reachable
+    // function is imported.
+    let mut used = BTreeSet::new();
+    let main = ModuleInfo::parse(main).context("failed to parse main module")?;
+    let reached = main.reached_imports(|_| true)?;
+    let mut unreached = BTreeSet::new();
+    for (index, &(module, name, _)) in main.imports.iter().enumerate() {
+        if reached.contains(&(index as u32)) {
+            used.insert(module);
+        } else if module == PREVIEW1 {
+            unreached.insert(name);
+        }
+    }
+    // The adapter's exports are reachable only if the main module may call
+    // them.  The other modules, which wire up the rest, export nothing by
+    // those names.
+    for module in others {
+        let info = ModuleInfo::parse(module).context("failed to parse module")?;
+        for index in info.reached_imports(|name| !unreached.contains(name))? {
+            used.insert(info.imports[index as usize].0);
+        }
+    }
+
+    let (resolve, world) = decode(&bytes)?;
+    let world = &resolve.worlds[world];
+    let mut pending = world
+        .imports
+        .iter()
+        .chain(&world.exports)
+        .filter_map(|(key, item)| match item {
+            WorldItem::Interface { id, .. } => {
+                let name = resolve.name_world_key(key);
+                (used.contains(name.as_str()) || world.exports.contains_key(key)).then_some(*id)
+            }
+            WorldItem::Type { id, .. } => resolve.type_interface_dep(*id),
+            WorldItem::Function(_) => None,
+        })
+        .collect::<Vec<_>>();
+    let mut kept = BTreeSet::new();
+    while let Some(id) = pending.pop() {
+        if kept.insert(id) {
+            pending.extend(resolve.interface_direct_deps(id));
+        }
+    }
+    let stubbed = world
+        .imports
+        .iter()
+        .filter_map(|(key, item)| match (key, item) {
+            (WorldKey::Interface(_), WorldItem::Interface { id, .. }) if !kept.contains(id) => {
+                Some(resolve.name_world_key(key))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if stubbed.is_empty() {
+        return Ok(stubbed);
+    }
+
+    let plug = provider(&provider_module(resolve, &stubbed, &[], |_, _| None)?)?;
+    compose_plugs(component, &[("the stubs".to_string(), plug)], component)?;
+    Ok(stubbed)
+}
+
+/// The core modules defined by a component, in order, not counting those of
+/// nested components.
+fn core_modules(component: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut modules = Vec::new();
+    let mut depth = 0;
+    for payload in Parser::new(0).parse_all(component) {
+        match payload.context("failed to parse component")? {
+            Payload::ModuleSection {
+                unchecked_range, ..
+            } => {
+                if depth == 0 {
+                    modules.push(&component[unchecked_range]);
+                }
+                depth += 1;
+            }
+            Payload::ComponentSection { .. } => depth += 1,
+            Payload::End(_) => depth -= 1,
+            _ => {}
+        }
+    }
+    Ok(modules)
+}
+
+/// The parts of a module needed to find which imported functions are
+/// reachable.
+struct ModuleInfo<'a> {
+    /// The module, name and type of each imported function, in order.
+    imports: Vec<(&'a str, &'a str, u32)>,
+    /// The name and index of each exported function.
+    exports: Vec<(&'a str, u32)>,
+    /// The bodies of the functions defined by the module, in order.
+    bodies: Vec<FunctionBody<'a>>,
+    /// Functions which are called on instantiation or referred to by globals.
+    roots: BTreeSet<u32>,
+    /// Functions placed in tables by element segments.
+    elements: BTreeSet<u32>,
+    /// Whether a table is imported or exported, so that it may be used from
+    /// outside the module.
+    shared_table: bool,
+}
+
+impl<'a> ModuleInfo<'a> {
+    fn parse(wasm: &'a [u8]) -> Result<Self> {
+        let mut info = Self {
+            imports: Vec::new(),
+            exports: Vec::new(),
+            bodies: Vec::new(),
+            roots: BTreeSet::new(),
+            elements: BTreeSet::new(),
+            shared_table: false,
+        };
+        for payload in Parser::new(0).parse_all(wasm) {
+            match payload? {
+                Payload::ImportSection(reader) => {
+                    for import in reader.into_imports() {
+                        let import = import?;
+                        match import.ty {
+                            TypeRef::Func(ty) | TypeRef::FuncExact(ty) => {
+                                info.imports.push((import.module, import.name, ty));
+                            }
+                            TypeRef::Table(_) => info.shared_table = true,
+                            _ => {}
+                        }
+                    }
+                }
+                Payload::ExportSection(reader) => {
+                    for export in reader {
+                        let export = export?;
+                        match export.kind {
+                            ExternalKind::Func => info.exports.push((export.name, export.index)),
+                            ExternalKind::Table => info.shared_table = true,
+                            _ => {}
+                        }
+                    }
+                }
+                Payload::StartSection { func, .. } => {
+                    info.roots.insert(func);
+                }
+                Payload::GlobalSection(reader) => {
+                    for global in reader {
+                        add_functions(&global?.init_expr, &mut info.roots)?;
+                    }
+                }
+                Payload::ElementSection(reader) => {
+                    for element in reader {
+                        let element = element?;
+                        // Declared segments only permit `ref.func`, which is
+                        // followed where it is used.
+                        if let ElementKind::Declared = element.kind {
+                            continue;
+                        }
+                        match element.items {
+                            ElementItems::Functions(functions) => {
+                                for function in functions {
+                                    info.elements.insert(function?);
+                                }
+                            }
+                            ElementItems::Expressions(_, exprs) => {
+                                for expr in exprs {
+                                    add_functions(&expr?, &mut info.elements)?;
+                                }
+                            }
+                        }
+                    }
+                }
+                Payload::CodeSectionEntry(body) => info.bodies.push(body),
+                _ => {}
+            }
+        }
+        Ok(info)
+    }
+
+    /// The indices of the imported functions which are reachable from the
+    /// start function, globals, and the exports for whose names `root`
+    /// returns `true`.
+    ///
+    /// The functions in tables are reachable only once a reachable function
+    /// reads a table, or if a table is shared with other modules.
+    fn reached_imports(&self, root: impl Fn(&str) -> bool) -> Result<BTreeSet<u32>> {
+        let import_count = self.imports.len() as u32;
+        let mut pending = self
+            .exports
+            .iter()
+            .filter(|(name, _)| root(name))
+            .map(|&(_, index)| index)
+            .chain(self.roots.iter().copied())
+            .collect::<Vec<_>>();
+        let mut tables_read = self.shared_table;
+        if tables_read {
+            pending.extend(&self.elements);
+        }
+
+        let mut reached = BTreeSet::new();
+        while let Some(function) = pending.pop() {
+            if !reached.insert(function) || function < import_count {
+                continue;
+            }
+            let Some(body) = self.bodies.get((function - import_count) as usize) else {
+                continue;
+            };
+            for op in body.get_operators_reader()? {
+                match op? {
+                    Operator::Call { function_index }
+                    | Operator::ReturnCall { function_index }
+                    | Operator::RefFunc { function_index } => pending.push(function_index),
+                    Operator::CallIndirect { .. }
+                    | Operator::ReturnCallIndirect { .. }
+                    | Operator::TableGet { .. }
+                        if !tables_read =>
+                    {
+                        tables_read = true;
+                        pending.extend(&self.elements);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(reached
+            .into_iter()
+            .filter(|&function| function < import_count)
+            .collect())
+    }
+}
+
+/// Add the functions referred to by `expr` to `functions`.
+fn add_functions(expr: &wasmparser::ConstExpr, functions: &mut BTreeSet<u32>) -> Result<()> {
+    for op in expr.get_operators_reader() {
+        if let Operator::RefFunc { function_index } = op? {
+            functions.insert(function_index);
+        }
+    }
+    Ok(())
+}
+
+/// The address in a [`provider_module`]'s memory at which its data begins.
+///
+/// The memory below is zeroed, so that a function may return a pointer to
+/// address 0 to stand for an empty list or a `none` result.
+pub const DATA_OFFSET: u32 = 16;
+
+/// Build a core module which implements `interfaces`, e.g.
+/// `wasi:cli/environment@0.2.6`, as defined in `resolve`, with the metadata
+/// needed to encode it as a component which exports them.
+///
+/// Each function is implemented by the body `define` returns given the name of
+/// its interface and the function, or by one which traps if that is `None`.
+/// `data` is placed in memory at [`DATA_OFFSET`].
+pub fn provider_module(
+    mut resolve: Resolve,
+    interfaces: &[String],
+    data: &[u8],
+    mut define: impl FnMut(&str, &wit_parser::Function) -> Option<Function>,
+) -> Result<Vec<u8>> {
+    let package = resolve.push_str(
+        "provider.wit",
+        &format!(
+            "package componentize-go:provider;\n\nworld provider {{\n{}}}\n",
+            interfaces
+                .iter()
+                .map(|interface| format!("  export {interface};\n"))
+                .collect::<String>()
+        ),
+    )?;
+    let world = resolve.select_world(&[package], Some("provider"))?;
+
+    let mut types = TypeSection::new();
+    let mut functions = FunctionSection::new();
+    let mut exports = ExportSection::new();
+    let mut code = CodeSection::new();
+    let mut add = |name: &str, params: &[ValType], results: &[ValType], body: &Function| {
+        types
+            .ty()
+            .function(params.iter().copied(), results.iter().copied());
+        functions.function(functions.len());
+        exports.export(name, ExportKind::Func, code.len());
+        code.function(body);
+    };
+
+    let core_type = |ty: &WasmType| match ty {
+        WasmType::I32 | WasmType::Pointer | WasmType::Length => ValType::I32,
+        WasmType::I64 | WasmType::PointerOrI64 => ValType::I64,
+        WasmType::F32 => ValType::F32,
+        WasmType::F64 => ValType::F64,
+    };
+    for (key, item) in &resolve.worlds[world].exports {
+        let WorldItem::Interface { id, .. } = item else {
+            continue;
+        };
+        let interface = resolve.name_world_key(key);
+        for function in resolve.interfaces[*id].functions.values() {
+            let signature = resolve.wasm_signature(AbiVariant::GuestExport, function);
+            let body = define(&interface, function).unwrap_or_else(|| {
+                let mut body = Function::new([]);
+                body.instructions().unreachable().end();
+                body
+            });
+            add(
+                &format!("{interface}#{}", function.name),
+                &signature.params.iter().map(core_type).collect::<Vec<_>>(),
+                &signature.results.iter().map(core_type).collect::<Vec<_>>(),
+                &body,
+            );
+        }
+    }
+    let mut realloc = Function::new([]);
+    realloc.instructions().unreachable().end();
+    add(
+        "cabi_realloc",
+        &[ValType::I32; 4],
+        &[ValType::I32],
+        &realloc,
+    );
+
+    let mut memories = MemorySection::new();
+    memories.memory(MemoryType {
+        minimum: u64::from(DATA_OFFSET + data.len() as u32).div_ceil(1 << 16),
+        maximum: None,
+        memory64: false,
+        shared: false,
+        page_size_log2: None,
+    });
+    exports.export("memory", ExportKind::Memory, 0);
+
+    let mut segments = DataSection::new();
+    segments.active(
+        0,
+        &ConstExpr::i32_const(DATA_OFFSET as i32),
+        data.iter().copied(),
+    );
+
+    let mut module = Module::new();
+    module
+        .section(&types)
+        .section(&functions)
+        .section(&memories)
+        .section(&exports)
+        .section(&code)
+        .section(&segments);
+    let mut module = module.finish();
+    wit_component::embed_component_metadata(&mut module, &resolve, world, StringEncoding::UTF8)?;
+    Ok(module)
+}
+
+/// Encode a [`provider_module`] as a component.
+pub fn provider(module: &[u8]) -> Result<Vec<u8>> {
+    ComponentEncoder::default()
+        .validate(true)
+        .module(module)?
+        .encode()
+        .context("failed to encode provider component")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{command::Adapter, testing};
+    use wasm_encoder::{
+        ElementSection, Elements, EntityType, ImportSection, TableSection, TableType,
+    };
+
+    /// A module importing `used` and `unused`, exporting a function which
+    /// calls only `used`.
+    fn module() -> Vec<u8> {
+        testing::module(
+            &[("host", "unused", &[], &[]), ("host", "used", &[], &[])],
+            "run",
+            &[1],
+        )
+    }
+
+    /// A module importing `a` and `b`, both placed in a table, and exporting
+    /// `run`, which calls through the table if `indirect`.
+    fn table_module(indirect: bool) -> Vec<u8> {
+        let mut types = TypeSection::new();
+        types.ty().function([], []);
+        let mut imports = ImportSection::new();
+        imports.import("host", "a", EntityType::Function(0));
+        imports.import("host", "b", EntityType::Function(0));
+        let mut functions = FunctionSection::new();
+        functions.function(0);
+        let mut tables = TableSection::new();
+        tables.table(TableType {
+            element_type: wasm_encoder::RefType::FUNCREF,
+            minimum: 2,
+            maximum: Some(2),
+            table64: false,
+            shared: false,
+        });
+        let mut exports = ExportSection::new();
+        exports.export("run", ExportKind::Func, 2);
+        let mut elements = ElementSection::new();
+        elements.active(
+            Some(0),
+            &ConstExpr::i32_const(0),
+            Elements::Functions([0, 1][..].into()),
+        );
+        let mut body = Function::new([]);
+        if indirect {
+            body.instructions().i32_const(1).call_indirect(0, 0);
+        }
+        body.instructions().end();
+        let mut code = CodeSection::new();
+        code.function(&body);
+
+        let mut module = Module::new();
+        module
+            .section(&types)
+            .section(&imports)
+            .section(&functions)
+            .section(&tables)
+            .section(&exports)
+            .section(&elements)
+            .section(&code);
+        module.finish()
+    }
+
+    #[test]
+    fn test_reached_imports() {
+        let wasm = module();
+        let info = ModuleInfo::parse(&wasm).unwrap();
+        assert_eq!(info.reached_imports(|_| true).unwrap(), [1].into());
+        assert!(info.reached_imports(|_| false).unwrap().is_empty());
+
+        // Functions in a table are reachable only if the table is read.
+        let wasm = table_module(false);
+        let info = ModuleInfo::parse(&wasm).unwrap();
+        assert!(info.reached_imports(|_| true).unwrap().is_empty());
+        let wasm = table_module(true);
+        let info = ModuleInfo::parse(&wasm).unwrap();
+        assert_eq!(info.reached_imports(|_| true).unwrap(), [0, 1].into());
+    }
+
+    /// The names of the interfaces imported by the component in `bytes`.
+    fn imports(bytes: &[u8]) -> Vec<String> {
+        let (resolve, world) = decode(bytes).unwrap();
+        resolve.worlds[world]
+            .imports
+            .keys()
+            .map(|key| resolve.name_world_key(key))
+            .collect()
+    }
+
+    #[test]
+    fn test_stub_unused_imports() {
+        // The environment is imported by the module, and so by the adapter,
+        // but never called.
+        use ValType::I32;
+        let module = testing::module(
+            &[
+                (PREVIEW1, "environ_get", &[I32; 2], &[I32]),
+                (PREVIEW1, "fd_write", &[I32; 4], &[I32]),
+            ],
+            "_start",
+            &[1],
+        );
+        let path = testing::temp_path("main.wasm");
+        fs::write(&path, testing::component(&module, Adapter::Command)).unwrap();
+        let before = imports(&fs::read(&path).unwrap());
+        let stubbed = stub_unused_imports(&path);
+        let after = imports(&fs::read(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+
+        let stubbed = stubbed.unwrap();
+        assert!(
+            stubbed
+                .iter()
+                .any(|name| name.starts_with("wasi:cli/environment@")),
+            "{stubbed:?}"
+        );
+        assert!(
+            after
+                .iter()
+                .all(|name| before.contains(name) && !stubbed.contains(name)),
+            "{before:?} {after:?}"
+        );
+        assert!(
+            after
+                .iter()
+                .any(|name| name.starts_with("wasi:cli/stdout@")),
+            "{after:?}"
+        );
+    }
+
+    #[test]
+    fn test_stub_traps() {
+        let component = testing::component(
+            &testing::module(
+                &[(PREVIEW1, "environ_get", &[ValType::I32; 2], &[ValType::I32])],
+                "_start",
+                &[0],
+            ),
+            Adapter::Command,
+        );
+        let (resolve, world) = decode(&component).unwrap();
+        let interfaces = resolve.worlds[world]
+            .imports
+            .keys()
+            .map(|key| resolve.name_world_key(key))
+            .filter(|name| name.starts_with("wasi:cli/environment@"))
+            .collect::<Vec<_>>();
+        let stubs = provider_module(resolve, &interfaces, &[], |_, _| None).unwrap();
+
+        let engine = wasmi::Engine::default();
+        let module = wasmi::Module::new(&engine, &stubs[..]).unwrap();
+        let mut store = wasmi::Store::new(&engine, ());
+        let instance = wasmi::Linker::<()>::new(&engine)
+            .instantiate(&mut store, &module)
+            .unwrap()
+            .start(&mut store)
+            .unwrap();
+        let function = instance
+            .get_typed_func::<(), i32>(&store, &format!("{}#get-environment", interfaces[0]))
+            .unwrap();
+        let error = function.call(&mut store, ()).unwrap_err();
+        assert_eq!(
+            error.as_trap_code(),
+            Some(wasmi::core::TrapCode::UnreachableCodeReached)
+        );
+    }
+}
@@ -0,0 +1,501 @@
+use crate::{
+    command::HostProfile,
+    config::{CONFIG_FILE_NAME, Origin, WitConfig},
+    utils::world_name,
+};
+use anyhow::{Context, Result, bail};
+use std::{fs, path::Path};
+use wit_component::DecodedWasm;
+use wit_parser::{PackageName, Resolve, WorldId, WorldItem, WorldKey};
+
+/// The WASI 0.2 interfaces, as patterns for [`matches`].
+const WASI_P2: &[&str] = &[
+    "wasi:cli/*@0.2",
+    "wasi:clocks/*@0.2",
+    "wasi:filesystem/*@0.2",
+    "wasi:http/*@0.2",
+    "wasi:io/*@0.2",
+    "wasi:random/*@0.2",
+    "wasi:sockets/*@0.2",
+];
+
+/// The WASI 0.3 release candidate supported by Wasmtime 44.
+const WASI_P3: &[&str] = &[
+    "wasi:cli/*@0.3.0-rc-2026-03-15",
+    "wasi:clocks/*@0.3.0-rc-2026-03-15",
+    "wasi:filesystem/*@0.3.0-rc-2026-03-15",
+    "wasi:http/*@0.3.0-rc-2026-03-15",
+    "wasi:random/*@0.3.0-rc-2026-03-15",
+    "wasi:sockets/*@0.3.0-rc-2026-03-15",
+];
+
+/// The interfaces imported by the `wasi:http/proxy@0.2.x` world.
+const WASI_HTTP_PROXY: &[&str] = &[
+    "wasi:cli/stderr@0.2",
+    "wasi:cli/stdin@0.2",
+    "wasi:cli/stdout@0.2",
+    "wasi:clocks/monotonic-clock@0.2",
+    "wasi:clocks/wall-clock@0.2",
+    "wasi:http/outgoing-handler@0.2",
+    "wasi:http/types@0.2",
+    "wasi:io/error@0.2",
+    "wasi:io/poll@0.2",
+    "wasi:io/streams@0.2",
+    "wasi:random/random@0.2",
+];
+
+/// The interfaces Spin 3 provides in addition to WASI 0.2.
+const SPIN: &[&str] = &[
+    "fermyon:spin/*",
+    "spin:*/*",
+    "wasi:config/*@0.2.0-draft-2024-09-27",
+    "wasi:keyvalue/*@0.2.0-draft2",
+];
+
+/// The HTTP handler interface exported by `wasi:http/proxy@0.2.x`
+/// components.
+const INCOMING_HANDLER: &[&str] = &["wasi:http/incoming-handler@0.2"];
+
+impl HostProfile {
+    /// Patterns matching the interfaces the host provides.
+    fn imports(self) -> Vec<&'static str> {
+        match self {
+            Self::Wasmtime44P2 => WASI_P2.to_vec(),
+            Self::Wasmtime44P3 => [WASI_P2, WASI_P3].concat(),
+            Self::Spin => [WASI_P2, SPIN].concat(),
+            Self::WasiHttpProxy => WASI_HTTP_PROXY.to_vec(),
+        }
+    }
+
+    /// Patterns matching the interfaces the host requires to be exported.
+    fn exports(self) -> &'static [&'static str] {
+        match self {
+            Self::Wasmtime44P2 | Self::Wasmtime44P3 => &[],
+            Self::Spin | Self::WasiHttpProxy => INCOMING_HANDLER,
+        }
+    }
+}
+
+/// Check that the component in `component` could be used where a component
+/// targeting `world` is expected: that its imports are a subset of the
+/// world's, and its exports a superset.
+pub fn check_target_world(component: &Path, resolve: &Resolve, world: WorldId) -> Result<()> {
+    let bytes =
+        fs::read(component).with_context(|| format!("failed to read `{}`", component.display()))?;
+    let (component_resolve, component_world) = decode(&bytes)?;
+    let target = world_name(resolve, world);
+
+    let provided = interfaces(resolve, world, Direction::Import);
+    let required = interfaces(resolve, world, Direction::Export);
+    let exported = interfaces(&component_resolve, component_world, Direction::Export);
+
+    let imported = interfaces(&component_resolve, component_world, Direction::Import);
+
+    let mut violations = Vec::new();
+    for import in &imported {
+        if !provided.iter().any(|other| compatible(import, other)) {
+            violations.push(format!(
+                "imports `{import}`, which `{target}` does not provide"
+            ));
+        }
+    }
+    for export in &required {
+        if !exported.iter().any(|other| compatible(export, other)) {
+            violations.push(format!(
+                "does not export `{export}`, which `{target}` requires"
+            ));
+        }
+    }
+    report(component, &target, violations)?;
+
+    // Check the types too.  The check requires names to match exactly, so
+    // names which only match semver-compatibly, e.g. `wasi:cli/stdout@0.2.6`
+    // for `wasi:cli/stdout@0.2.0`, are aligned first.
+    wit_component::targets(&align_versions(resolve, &component_resolve), world, &bytes)
+        .with_context(|| {
+            format!(
+                "`{}` does not match the types of `{target}`",
+                component.display()
+            )
+        })?;
+    Ok(())
+}
+
+/// A copy of `resolve` in which each package is renamed to the version of the
+/// same package in `component`, if that is a different but semver-compatible
+/// version.
+fn align_versions(resolve: &Resolve, component: &Resolve) -> Resolve {
+    let mut aligned = resolve.clone();
+    for (id, package) in resolve.packages.iter() {
+        let Some(version) = &package.name.version else {
+            continue;
+        };
+        let track = PackageName::version_compat_track(version);
+        let names = component
+            .package_names
+            .keys()
+            .filter(|name| {
+                name.namespace == package.name.namespace
+                    && name.name == package.name.name
+                    && name.version.as_ref().is_some_and(|other| {
+                        other != version && PackageName::version_compat_track(other) == track
+                    })
+            })
+            .collect::<Vec<_>>();
+        if let [name] = names[..]
+            && !aligned.package_names.contains_key(name)
+        {
+            aligned.package_names.shift_remove(&package.name);
+            aligned.package_names.insert(name.clone(), id);
+            aligned.packages[id].name = name.clone();
+        }
+    }
+    aligned
+}
+
+/// Check that the component in `component` only imports interfaces provided
+/// by `profile` and exports those it requires.
+pub fn check_host_profile(component: &Path, profile: HostProfile) -> Result<()> {
+    let bytes =
+        fs::read(component).with_context(|| format!("failed to read `{}`", component.display()))?;
+    let (resolve, world) = decode(&bytes)?;
+    let target = profile.name();
+
+    let provided = profile.imports();
+    let exported = interfaces(&resolve, world, Direction::Export);
+
+    let mut violations = Vec::new();
+    for import in interfaces(&resolve, world, Direction::Import) {
+        if !provided.iter().any(|pattern| matches(pattern, &import)) {
+            violations.push(format!(
+                "imports `{import}`, which `{target}` does not provide"
+            ));
+        }
+    }
+    for pattern in profile.exports() {
+        if !exported.iter().any(|export| matches(pattern, export)) {
+            violations.push(format!(
+                "does not export `{pattern}.x`, which `{target}` requires"
+            ));
+        }
+    }
+    report(component, target, violations)
+}
+
+/// Check the imports of the component in `component` against the import rules
+/// in `config`: each import must match one of the `allow_imports` patterns, if
+/// there are any, and none of the `deny_imports` patterns.
+///
+/// Violations are attributed to the worlds in `config` which import the
+/// offending interface, or else to the snapshot adapter.  `world` is the
+/// world the component was built for.
+pub fn check_import_policy(
+    component: &Path,
+    resolve: &Resolve,
+    world: WorldId,
+    config: &WitConfig,
+) -> Result<()> {
+    if config.allow_imports.is_empty() && config.deny_imports.is_empty() {
+        return Ok(());
+    }
+
+    let bytes =
+        fs::read(component).with_context(|| format!("failed to read `{}`", component.display()))?;
+    let (component_resolve, component_world) = decode(&bytes)?;
+
+    // The worlds which may have contributed imports, and where they came from.
+    let worlds = if config.worlds.is_empty() {
+        vec![(world, None)]
+    } else {
+        config
+            .worlds
+            .iter()
+            .filter_map(|name| {
+                // `--world` accepts the bare name of a world in the main
+                // package as well as a fully-qualified one.
+                let (id, _) = resolve.worlds.iter().find(|(id, item)| {
+                    item.name == *name || compatible(&world_name(resolve, *id), name)
+                })?;
+                Some((id, config.world_origins.get(name)))
+            })
+            .collect()
+    };
+
+    let mut violations = Vec::new();
+    for import in interfaces(&component_resolve, component_world, Direction::Import) {
+        let reason = if let Some(rule) = config
+            .deny_imports
+            .iter()
+            .find(|rule| matches(&pattern(&rule.pattern), &import))
+        {
+            format!("denied by `{}` {}", rule.pattern, source(&rule.origin))
+        } else if !config.allow_imports.is_empty()
+            && !config
+                .allow_imports
+                .iter()
+                .any(|rule| matches(&pattern(&rule.pattern), &import))
+        {
+            "not allowed by any `allow_imports` pattern".to_string()
+        } else {
+            continue;
+        };
+
+        let sources = worlds
+            .iter()
+            .filter(|(id, _)| {
+                interfaces(resolve, *id, Direction::Import)
+                    .iter()
+                    .any(|other| compatible(&import, other))
+            })
+            .map(|(id, origin)| match origin {
+                Some(origin) => format!("world `{}` {}", world_name(resolve, *id), source(origin)),
+                None => format!("world `{}`", world_name(resolve, *id)),
+            })
+            .collect::<Vec<_>>();
+        let source = if sources.is_empty() {
+            "the WASI Preview 1 adapter, on behalf of the Go runtime".to_string()
+        } else {
+            sources.join(" and ")
+        };
+
+        violations.push(format!(
+            "imports `{import}` ({reason}), required by {source}"
+        ));
+    }
+
+    if violations.is_empty() {
+        return Ok(());
+    }
+    bail!(
+        "`{}` violates the import policy:{}",
+        component.display(),
+        violations
+            .iter()
+            .map(|violation| format!("\n  - {violation}"))
+            .collect::<String>()
+    )
+}
+
+/// Describe where a world or import rule came from.
+fn source(origin: &Origin) -> String {
+    match origin {
+        Origin::CommandLine => "from the command line".into(),
+        Origin::Module(module) => format!("in the `{CONFIG_FILE_NAME}` of Go module `{module}`"),
+    }
+}
+
+/// Expand a policy pattern naming a whole package, e.g. `wasi:filesystem` or
+/// `wasi:filesystem@0.2`, to one matching each of its interfaces.
+fn pattern(pattern: &str) -> String {
+    let (name, version) = split_version(pattern);
+    let name = if name.contains(':') && !name.contains('/') {
+        format!("{name}/*")
+    } else {
+        name.to_string()
+    };
+    match version {
+        Some(version) => format!("{name}@{version}"),
+        None => name,
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<(Resolve, WorldId)> {
+    match wit_component::decode(bytes).context("failed to decode component")? {
+        DecodedWasm::Component(resolve, world) => Ok((resolve, world)),
+        DecodedWasm::WitPackage(..) => bail!("expected a component, found a WIT package"),
+    }
+}
+
+fn report(component: &Path, target: &str, violations: Vec<String>) -> Result<()> {
+    if violations.is_empty() {
+        return Ok(());
+    }
+    bail!(
+        "`{}` does not fit `{target}`:{}",
+        component.display(),
+        violations
+            .iter()
+            .map(|violation| format!("\n  - {violation}"))
+            .collect::<String>()
+    )
+}
+
+#[derive(Clone, Copy)]
+enum Direction {
+    Import,
+    Export,
+}
+
+/// The names of the interfaces and functions imported or exported by `world`,
+/// e.g. `wasi:cli/environment@0.2.6` or `run`.
+fn interfaces(resolve: &Resolve, world: WorldId, direction: Direction) -> Vec<String> {
+    let items = match direction {
+        Direction::Import => &resolve.worlds[world].imports,
+        Direction::Export => &resolve.worlds[world].exports,
+    };
+    items
+        .iter()
+        .filter_map(|(key, item)| match (key, item) {
+            (_, WorldItem::Type { .. }) => None,
+            (WorldKey::Interface(id), _) => resolve.id_of(*id),
+            (WorldKey::Name(name), _) => Some(name.clone()),
+        })
+        .collect()
+}
+
+/// Whether `a` and `b` name semver-compatible versions of the same interface.
+fn compatible(a: &str, b: &str) -> bool {
+    let track = |version: Option<&str>| {
+        version
+            .and_then(|version| semver::Version::parse(version).ok())
+            .map(|version| PackageName::version_compat_track(&version))
+    };
+    let (a, a_version) = split_version(a);
+    let (b, b_version) = split_version(b);
+    a == b && track(a_version) == track(b_version)
+}
+
+/// Whether the interface `name`, e.g. `wasi:cli/environment@0.2.6`, matches
+/// `pattern`, e.g. `wasi:cli/*@0.2`.
+///
+/// Either the package or the interface may be `*` in a pattern.  A pattern
+/// without a version matches any version, while a version matches any version
+/// it is a prefix of, up to a `.`.
+fn matches(pattern: &str, name: &str) -> bool {
+    let (pattern, pattern_version) = split_version(pattern);
+    let (name, version) = split_version(name);
+
+    let version_matches = match (pattern_version, version) {
+        (None, _) => true,
+        (Some(expected), Some(version)) => {
+            version == expected
+                || version
+                    .strip_prefix(expected)
+                    .is_some_and(|rest| rest.starts_with('.'))
+        }
+        (Some(_), None) => false,
+    };
+
+    let (Some((namespace, package, interface)), Some(expected)) =
+        (split_name(name), split_name(pattern))
+    else {
+        // Functions imported directly by a world, e.g. `run`, only match
+        // themselves.
+        return pattern == name && pattern_version.is_none();
+    };
+    version_matches
+        && namespace == expected.0
+        && (expected.1 == "*" || package == expected.1)
+        && (expected.2 == "*" || interface == expected.2)
+}
+
+/// Split `namespace:package/interface` into its parts.
+fn split_name(name: &str) -> Option<(&str, &str, &str)> {
+    let (namespace, rest) = name.split_once(':')?;
+    let (package, interface) = rest.split_once('/')?;
+    Some((namespace, package, interface))
+}
+
+/// Split `name@version` into its parts.
+fn split_version(name: &str) -> (&str, Option<&str>) {
+    match name.split_once('@') {
+        Some((name, version)) => (name, Some(version)),
+        None => (name, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{command::Adapter, testing};
+    use wasm_encoder::ValType;
+
+    #[test]
+    fn test_check_target_world_compatible() {
+        // A target world like the component's own, but naming older patch
+        // versions of each package.
+        let component = testing::component(
+            &testing::module(
+                &[(
+                    "wasi_snapshot_preview1",
+                    "fd_write",
+                    &[ValType::I32; 4],
+                    &[ValType::I32],
+                )],
+                "_start",
+                &[0],
+            ),
+            Adapter::Command,
+        );
+        let (mut resolve, world) = decode(&component).unwrap();
+        for (_, package) in resolve.packages.iter_mut() {
+            if let Some(version) = &mut package.name.version {
+                version.patch = 0;
+            }
+        }
+        resolve.package_names = resolve
+            .packages
+            .iter()
+            .map(|(id, package)| (package.name.clone(), id))
+            .collect();
+
+        let path = testing::temp_path("main.wasm");
+        fs::write(&path, component).unwrap();
+        let matching = check_target_world(&path, &resolve, world);
+
+        // The target lacks a function the component imports, which only the
+        // check of the types finds.
+        let stdout = resolve
+            .interfaces
+            .iter()
+            .find(|(_, interface)| interface.name.as_deref() == Some("stdout"))
+            .map(|(id, _)| id)
+            .unwrap();
+        resolve.interfaces[stdout].functions.clear();
+        let mismatching = check_target_world(&path, &resolve, world);
+        fs::remove_file(&path).unwrap();
+
+        matching.unwrap();
+        let error = format!("{:#}", mismatching.unwrap_err());
+        assert!(error.contains("does not match the types"), "{error}");
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("wasi:cli/*@0.2", "wasi:cli/environment@0.2.6"));
+        assert!(matches("wasi:cli/stdout@0.2", "wasi:cli/stdout@0.2.0"));
+        assert!(!matches("wasi:cli/stdout@0.2", "wasi:cli/stderr@0.2.0"));
+        assert!(!matches("wasi:cli/*@0.2", "wasi:cli/environment@0.20.0"));
+        assert!(!matches(
+            "wasi:cli/*@0.2",
+            "wasi:cli/environment@0.3.0-rc-2026-03-15"
+        ));
+        assert!(matches(
+            "wasi:http/*@0.3.0-rc-2026-03-15",
+            "wasi:http/types@0.3.0-rc-2026-03-15"
+        ));
+        assert!(matches("spin:*/*", "spin:postgres/postgres@3.0.0"));
+        assert!(!matches("spin:*/*", "fermyon:spin/llm@2.0.0"));
+        assert!(!matches("wasi:cli/*@0.2", "run"));
+        assert!(matches("run", "run"));
+    }
+
+    #[test]
+    fn test_pattern() {
+        assert_eq!(pattern("wasi:filesystem"), "wasi:filesystem/*");
+        assert_eq!(pattern("wasi:filesystem@0.2"), "wasi:filesystem/*@0.2");
+        assert_eq!(pattern("wasi:cli/stdout"), "wasi:cli/stdout");
+        assert_eq!(pattern("run"), "run");
+    }
+
+    #[test]
+    fn test_compatible() {
+        assert!(compatible("wasi:cli/run@0.2.0", "wasi:cli/run@0.2.6"));
+        assert!(!compatible(
+            "wasi:cli/run@0.2.0",
+            "wasi:cli/run@0.3.0-rc-2026-03-15"
+        ));
+        assert!(!compatible("wasi:cli/run@0.2.0", "wasi:cli/exit@0.2.0"));
+        assert!(compatible("run", "run"));
+    }
+}
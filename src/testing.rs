@@ -0,0 +1,108 @@
+//! Fixtures shared by the unit tests.
+
+use crate::{
+    command::Adapter,
+    utils::{dummy_wit, module_to_component},
+};
+use std::{
+    env, fs,
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicU32, Ordering},
+};
+use wasm_encoder::{
+    CodeSection, EntityType, ExportKind, ExportSection, Function, FunctionSection, ImportSection,
+    MemorySection, MemoryType, Module, NameMap, NameSection, TypeSection, ValType,
+};
+
+/// An imported function: its module, name, parameters and results.
+pub type Import<'a> = (&'a str, &'a str, &'a [ValType], &'a [ValType]);
+
+/// A path in the temporary directory which is unique to this call, ending in
+/// `name`.
+pub fn temp_path(name: &str) -> PathBuf {
+    static NEXT: AtomicU32 = AtomicU32::new(0);
+    let unique = NEXT.fetch_add(1, Ordering::Relaxed);
+    env::temp_dir().join(format!("componentize-go-{}-{unique}-{name}", process::id()))
+}
+
+/// A core module shaped like those Go produces: it exports a memory and a
+/// function named `export`, which calls each of the `imports` whose index is
+/// in `calls` with zeroes, and it names its functions in a name section.
+pub fn module(imports: &[Import], export: &str, calls: &[u32]) -> Vec<u8> {
+    let mut types = TypeSection::new();
+    let mut section = ImportSection::new();
+    let mut names = NameMap::new();
+    for (index, (module, name, params, results)) in imports.iter().enumerate() {
+        types
+            .ty()
+            .function(params.iter().copied(), results.iter().copied());
+        section.import(module, name, EntityType::Function(index as u32));
+        names.append(index as u32, name);
+    }
+    let export_index = imports.len() as u32;
+    types.ty().function([], []);
+    names.append(export_index, export);
+
+    let mut functions = FunctionSection::new();
+    functions.function(export_index);
+    let mut memories = MemorySection::new();
+    memories.memory(MemoryType {
+        minimum: 1,
+        maximum: None,
+        memory64: false,
+        shared: false,
+        page_size_log2: None,
+    });
+    let mut exports = ExportSection::new();
+    exports.export(export, ExportKind::Func, export_index);
+    exports.export("memory", ExportKind::Memory, 0);
+
+    let mut body = Function::new([]);
+    let mut instructions = body.instructions();
+    for &call in calls {
+        let (_, _, params, results) = imports[call as usize];
+        for param in params {
+            match param {
+                ValType::I64 => instructions.i64_const(0),
+                ValType::F32 => instructions.f32_const(0.0.into()),
+                ValType::F64 => instructions.f64_const(0.0.into()),
+                _ => instructions.i32_const(0),
+            };
+        }
+        instructions.call(call);
+        for _ in results.iter() {
+            instructions.drop();
+        }
+    }
+    instructions.end();
+    let mut code = CodeSection::new();
+    code.function(&body);
+
+    let mut name_section = NameSection::new();
+    name_section.functions(&names);
+
+    let mut module = Module::new();
+    module
+        .section(&types)
+        .section(&section)
+        .section(&functions)
+        .section(&memories)
+        .section(&exports)
+        .section(&code)
+        .section(&name_section);
+    module.finish()
+}
+
+/// Encode `module` as a component, with no WIT world, using the embedded
+/// adapter of the given kind.
+pub fn component(module: &[u8], adapter: Adapter) -> Vec<u8> {
+    let path = temp_path("component.wasm");
+    fs::write(&path, module).unwrap();
+    let (resolve, world) = dummy_wit();
+    let result = module_to_component(&path, &resolve, world, None, adapter, true);
+    let bytes = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    result.unwrap();
+    bytes
+}
@@ -0,0 +1,782 @@
+use crate::config::ComponentizeGoConfig;
+use anyhow::{Context, Result, bail};
+use bzip2::read::BzDecoder;
+use std::{
+    env,
+    fs::{self, File, TryLockError},
+    io::{Cursor, IsTerminal, Read},
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+use tar::Archive;
+
+/// The patched Go release used when no other is selected.
+pub const DEFAULT_VERSION: &str = "go1.25.5-wasi-on-idle-v2";
+
+/// Patched Go releases known to work with this version of `componentize-go`.
+pub const KNOWN_VERSIONS: &[&str] = &[DEFAULT_VERSION];
+
+/// SHA-256 digests of the release archives, by version and distribution name.
+///
+/// Archives for versions and platforms listed here are rejected if their
+/// digest does not match, and archives of [`KNOWN_VERSIONS`] are rejected if
+/// they are not listed.  When publishing a new release, add an entry for each
+/// of its archives, as printed by `sha256sum *.tbz`.
+const CHECKSUMS: &[(&str, &str, &str)] = &[];
+
+/// Prefix of the directories into which archives are extracted before being
+/// moved into place.
+const STAGING_PREFIX: &str = ".staging-";
+
+/// The platforms, using Go's naming conventions, for which patched Go releases
+/// are published.
+const RELEASE_PLATFORMS: &[(&str, &str)] = &[
+    ("darwin", "amd64"),
+    ("darwin", "arm64"),
+    ("linux", "amd64"),
+    ("linux", "arm64"),
+    ("windows", "amd64"),
+    ("windows", "arm64"),
+];
+
+/// Directories in the `componentize-go` cache directory which were used by
+/// earlier versions to store a single patched Go release, with that release.
+const LEGACY_DIRS: &[(&str, &str)] = &[("v2", "go1.25.5-wasi-on-idle-v2")];
+
+/// The lock file which earlier versions of `componentize-go` kept in each of
+/// [`LEGACY_DIRS`] while downloading into it.
+const LEGACY_LOCK: &str = "lock";
+
+/// Extension of the per-version lock files held while installing.
+const LOCK_EXTENSION: &str = "lock";
+
+/// The default location from which release archives are downloaded.
+pub const DEFAULT_MIRROR: &str = "https://github.com/dicej/go/releases/download";
+
+/// The environment variable which overrides the download location.
+pub const MIRROR_ENV: &str = "COMPONENTIZE_GO_TOOLCHAIN_MIRROR";
+
+/// How long to wait by default for another process to finish installing the
+/// same toolchain.
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// The environment variable which overrides [`DEFAULT_LOCK_TIMEOUT`], in
+/// seconds.
+pub const LOCK_TIMEOUT_ENV: &str = "COMPONENTIZE_GO_LOCK_TIMEOUT";
+
+/// Where to obtain a toolchain which is not yet installed.
+#[derive(Default)]
+pub struct InstallOptions {
+    /// A local release archive to install instead of downloading one.
+    pub archive: Option<PathBuf>,
+    /// A Go source tree from which to build the toolchain instead of
+    /// downloading a release, e.g. a checkout of https://github.com/dicej/go.
+    pub source: Option<PathBuf>,
+    /// If `true`, fail rather than downloading anything.
+    pub offline: bool,
+    /// How long to wait for another process installing the same toolchain;
+    /// see [`lock_timeout`] for the default.
+    pub lock_timeout: Option<Duration>,
+}
+
+/// A patched Go release, which may or may not be installed.
+pub struct Toolchain {
+    /// The release tag, e.g. `go1.25.5-wasi-on-idle-v2`.
+    pub version: String,
+    /// The directory into which the release is extracted.
+    pub dir: PathBuf,
+    /// The name of the distribution for the current platform, which is also
+    /// the name of the top-level directory in the release archive.
+    name: String,
+}
+
+impl Toolchain {
+    pub fn new(version: &str) -> Result<Self> {
+        if version.is_empty() || version == "." || version == ".." || version.contains(['/', '\\'])
+        {
+            bail!("invalid toolchain version `{version}`");
+        }
+
+        Ok(Self {
+            version: version.to_string(),
+            dir: root()?.join(version),
+            name: distribution_name(),
+        })
+    }
+
+    /// The toolchain selected for the current project: the one named by the
+    /// `toolchain` field of `./componentize-go.toml`, if any, or else
+    /// [`DEFAULT_VERSION`].
+    pub fn selected() -> Result<Self> {
+        Self::new(&project_version()?.unwrap_or_else(|| DEFAULT_VERSION.to_string()))
+    }
+
+    /// The path to the `go` binary.
+    pub fn bin(&self) -> PathBuf {
+        self.dir.join(&self.name).join("bin").join("go")
+    }
+
+    pub fn is_installed(&self) -> bool {
+        self.bin().exists()
+    }
+
+    /// Why this toolchain cannot be downloaded for the current platform, if it
+    /// cannot.
+    pub fn download_blocker(&self) -> Option<String> {
+        if let Err(e) = check_release_platform() {
+            return e.to_string().lines().next().map(str::to_string);
+        }
+        (KNOWN_VERSIONS.contains(&self.version.as_str())
+            && checksum(&self.version, &self.name).is_none())
+        .then(|| {
+            format!(
+                "no SHA-256 digest is pinned for {}/{}.tbz, so it would be rejected",
+                self.version, self.name
+            )
+        })
+    }
+
+    /// The file locked while this toolchain is being installed.
+    pub fn lock_path(&self) -> PathBuf {
+        let mut path = self.dir.clone().into_os_string();
+        path.push(format!(".{LOCK_EXTENSION}"));
+        path.into()
+    }
+
+    /// Install the toolchain, unless it is already installed, from the source
+    /// described by `options`.
+    pub fn install(&self, options: &InstallOptions) -> Result<()> {
+        let root = root()?;
+        fs::create_dir_all(&root)?;
+
+        // Grab a lock to avoid concurrent installations of this version
+        let timeout = match options.lock_timeout {
+            Some(timeout) => timeout,
+            None => lock_timeout()?,
+        };
+        migrate_legacy(timeout)?;
+        let _lock = self.lock(timeout)?;
+
+        if self.is_installed() {
+            return Ok(());
+        }
+
+        if let Some(archive) = &options.archive {
+            eprintln!("Installing patched Go from {}.", archive.display());
+
+            let content = fs::read(archive)
+                .with_context(|| format!("failed to read `{}`", archive.display()))?;
+
+            self.verify(&content)
+                .with_context(|| format!("failed to verify `{}`", archive.display()))?;
+
+            return self.extract(&content);
+        }
+
+        if let Some(source) = &options.source {
+            return self.build(source);
+        }
+
+        if options.offline {
+            bail!(
+                "patched Go `{0}` is not installed, and `--offline` prevents downloading it; \
+                 install it from a local archive with `--go-archive <FILE>` or \
+                 `componentize-go toolchain install {0} --from <FILE>`",
+                self.version
+            );
+        }
+
+        check_release_platform()?;
+
+        let url = format!("{}/{}/{}.tbz", mirror()?, self.version, self.name);
+
+        let content = download(&url)?;
+
+        self.verify(&content)
+            .with_context(|| format!("failed to verify archive downloaded from {url}"))?;
+
+        self.extract(&content)
+    }
+
+    /// Take the lock for this toolchain, waiting up to `timeout` for any other
+    /// process which holds it.
+    fn lock(&self, timeout: Duration) -> Result<File> {
+        let path = self.lock_path();
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("failed to open `{}`", path.display()))?;
+
+        let start = Instant::now();
+        let mut waiting = false;
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(file),
+                Err(TryLockError::WouldBlock) => {}
+                Err(TryLockError::Error(e)) => {
+                    return Err(e).with_context(|| format!("failed to lock `{}`", path.display()));
+                }
+            }
+
+            if start.elapsed() >= timeout {
+                bail!(
+                    "timed out after {}s waiting for another componentize-go to finish \
+                     installing `{}`; if none is running, something else holds a lock on `{}`.\n\
+                     Use `--lock-timeout <SECONDS>` or the {LOCK_TIMEOUT_ENV} environment \
+                     variable to wait longer.",
+                    timeout.as_secs(),
+                    self.version,
+                    path.display()
+                );
+            }
+            if !waiting {
+                eprintln!(
+                    "Waiting for another componentize-go to finish downloading `{}`...",
+                    self.version
+                );
+                waiting = true;
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+    }
+
+    /// Check the SHA-256 digest of a release archive against the one pinned
+    /// for this version and platform.
+    ///
+    /// Every archive of a known version must have a pinned digest; archives of
+    /// other versions are accepted without one.
+    fn verify(&self, archive: &[u8]) -> Result<()> {
+        self.verify_digest(archive, checksum(&self.version, &self.name))
+    }
+
+    /// Check the SHA-256 digest of a release archive against `expected`.
+    fn verify_digest(&self, archive: &[u8], expected: Option<&str>) -> Result<()> {
+        let actual = sha256(archive);
+        match expected {
+            Some(expected) if actual == expected => Ok(()),
+            Some(expected) => bail!(
+                "SHA-256 mismatch for {}/{}.tbz: expected {expected}, found {actual}; \
+                 the download may be truncated or tampered with",
+                self.version,
+                self.name
+            ),
+            None if KNOWN_VERSIONS.contains(&self.version.as_str()) => bail!(
+                "no SHA-256 digest is pinned for {}/{}.tbz, so the archive (SHA-256 {actual}) \
+                 cannot be verified.\n\
+                 Use `--go` to specify a patched Go, or build one with \
+                 `componentize-go toolchain install --source <DIR>`.",
+                self.version,
+                self.name
+            ),
+            None => {
+                eprintln!(
+                    "Note: no checksum is known for {}/{}.tbz; \
+                     unable to verify archive with SHA-256 {actual}.",
+                    self.version, self.name
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Extract a release archive into place.
+    fn extract(&self, archive: &[u8]) -> Result<()> {
+        eprintln!("Extracting patched Go to {}.", self.dir.display());
+
+        self.stage(|staging| {
+            Archive::new(BzDecoder::new(Cursor::new(archive)))
+                .unpack(staging)
+                .context("failed to extract archive")?;
+            if !staging.join(&self.name).join("bin").join("go").exists() {
+                bail!("archive does not contain `{}/bin/go`", self.name);
+            }
+            Ok(())
+        })
+    }
+
+    /// Build the toolchain from a copy of the Go source tree in `source`,
+    /// using its `make.bash` (or `make.bat`) script.
+    fn build(&self, source: &Path) -> Result<()> {
+        let script = if cfg!(windows) {
+            "make.bat"
+        } else {
+            "make.bash"
+        };
+        if !source.join("src").join(script).exists() {
+            bail!(
+                "`{}` does not look like a Go source tree: `src/{script}` not found",
+                source.display()
+            );
+        }
+
+        eprintln!(
+            "Building patched Go from {} into {}.",
+            source.display(),
+            self.dir.display()
+        );
+
+        self.stage(|staging| {
+            let goroot = staging.join(&self.name);
+            copy_dir(source, &goroot)?;
+
+            let mut command = if cfg!(windows) {
+                let mut command = Command::new("cmd");
+                command.args(["/C", script]);
+                command
+            } else {
+                let mut command = Command::new("bash");
+                command.arg(script);
+                command
+            };
+            let status = command
+                .current_dir(goroot.join("src"))
+                .env_remove("GOROOT")
+                .status()
+                .with_context(|| format!("failed to run `{script}`"))?;
+            if !status.success() {
+                bail!(
+                    "`{script}` failed ({status}); note that building Go requires an existing \
+                     Go installation, found in PATH or via `GOROOT_BOOTSTRAP`"
+                );
+            }
+            if !goroot.join("bin").join("go").exists() {
+                bail!("`{script}` did not produce `bin/go`");
+            }
+            Ok(())
+        })
+    }
+
+    /// Populate a staging directory using `populate` and then move it into
+    /// place, so that an interrupted or failed installation never leaves a
+    /// partially populated toolchain behind.
+    fn stage(&self, populate: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+        let root = root()?;
+        let staging = root.join(format!(
+            "{STAGING_PREFIX}{}-{}",
+            self.version,
+            std::process::id()
+        ));
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        fs::create_dir_all(&staging)?;
+
+        let result = populate(&staging).and_then(|()| {
+            // Clear out any incomplete installation left behind by an
+            // earlier version of componentize-go.
+            if self.dir.exists() {
+                fs::remove_dir_all(&self.dir)?;
+            }
+            fs::rename(&staging, &self.dir).with_context(|| {
+                format!(
+                    "failed to move `{}` to `{}`",
+                    staging.display(),
+                    self.dir.display()
+                )
+            })
+        });
+
+        if result.is_err() {
+            _ = fs::remove_dir_all(&staging);
+        }
+        result
+    }
+
+    /// Remove the toolchain, first waiting up to `timeout` for any process
+    /// installing it.
+    pub fn remove(&self, timeout: Duration) -> Result<()> {
+        let _lock = self.lock(timeout)?;
+        fs::remove_dir_all(&self.dir)
+            .with_context(|| format!("failed to remove `{}`", self.dir.display()))
+    }
+}
+
+/// The version named by the `toolchain` field of `./componentize-go.toml`, if
+/// any.
+pub fn project_version() -> Result<Option<String>> {
+    let config = ComponentizeGoConfig::read(&env::current_dir()?)?;
+    Ok(config.and_then(|config| config.toolchain))
+}
+
+/// The base URL from which release archives are downloaded, as
+/// `<base>/<version>/<name>.tbz`: the value of the
+/// `COMPONENTIZE_GO_TOOLCHAIN_MIRROR` environment variable, else the
+/// `toolchain_mirror` field of `./componentize-go.toml`, else
+/// [`DEFAULT_MIRROR`].
+pub fn mirror() -> Result<String> {
+    let mirror = match env::var(MIRROR_ENV) {
+        Ok(mirror) if !mirror.is_empty() => mirror,
+        _ => ComponentizeGoConfig::read(&env::current_dir()?)?
+            .and_then(|config| config.toolchain_mirror)
+            .unwrap_or_else(|| DEFAULT_MIRROR.to_string()),
+    };
+    Ok(mirror.trim_end_matches('/').to_string())
+}
+
+/// How long to wait for another process to finish installing a toolchain:
+/// the value of the `COMPONENTIZE_GO_LOCK_TIMEOUT` environment variable, in
+/// seconds, else [`DEFAULT_LOCK_TIMEOUT`].
+pub fn lock_timeout() -> Result<Duration> {
+    match env::var(LOCK_TIMEOUT_ENV) {
+        Ok(seconds) if !seconds.is_empty() => seconds
+            .trim()
+            .parse()
+            .map(Duration::from_secs)
+            .with_context(|| format!("invalid {LOCK_TIMEOUT_ENV} `{seconds}`; expected seconds")),
+        _ => Ok(DEFAULT_LOCK_TIMEOUT),
+    }
+}
+
+/// All toolchains found in the cache directory, sorted by version, including
+/// any which were only partially extracted.
+pub fn installed() -> Result<Vec<Toolchain>> {
+    let root = root()?;
+    let mut toolchains = Vec::new();
+    if let Ok(entries) = fs::read_dir(&root) {
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_dir()
+                && let Some(version) = entry.file_name().to_str()
+                && !version.starts_with(STAGING_PREFIX)
+            {
+                toolchains.push(Toolchain::new(version)?);
+            }
+        }
+    }
+    toolchains.sort_by(|a, b| a.version.cmp(&b.version));
+    Ok(toolchains)
+}
+
+/// Remove directories left behind by earlier versions of `componentize-go` or
+/// by interrupted extractions, returning the paths which were removed.
+pub fn remove_stale() -> Result<Vec<PathBuf>> {
+    let root = root()?;
+    let mut removed = Vec::new();
+    for entry in fs::read_dir(&root).into_iter().flatten() {
+        let entry = entry?;
+        if entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.starts_with(STAGING_PREFIX))
+        {
+            fs::remove_dir_all(entry.path())
+                .with_context(|| format!("failed to remove `{}`", entry.path().display()))?;
+            removed.push(entry.path());
+        }
+    }
+
+    for (name, _) in LEGACY_DIRS {
+        let dir = legacy_base()?.join(name);
+        if !dir.exists() {
+            continue;
+        }
+        // Leave the directory to an earlier version still downloading into it.
+        let Some(_lock) = try_lock_legacy(&dir)? else {
+            continue;
+        };
+        fs::remove_dir_all(&dir)
+            .with_context(|| format!("failed to remove `{}`", dir.display()))?;
+        removed.push(dir);
+    }
+    Ok(removed)
+}
+
+/// Move each release installed by an earlier version of `componentize-go` in
+/// one of [`LEGACY_DIRS`] to where this version looks for it, unless that
+/// release is already installed here, waiting up to `timeout` for any process
+/// installing it.
+///
+/// The rest of the legacy directory is left for [`remove_stale`].
+pub fn migrate_legacy(timeout: Duration) -> Result<()> {
+    for (name, version) in LEGACY_DIRS {
+        let dir = legacy_base()?.join(name);
+        let toolchain = Toolchain::new(version)?;
+        let legacy = dir.join(&toolchain.name);
+        if toolchain.is_installed() || !legacy.join("bin").join("go").exists() {
+            continue;
+        }
+        // An earlier version may still be extracting the release.
+        let Some(_legacy_lock) = try_lock_legacy(&dir)? else {
+            continue;
+        };
+
+        fs::create_dir_all(root()?)?;
+        let _lock = toolchain.lock(timeout)?;
+        if toolchain.is_installed() {
+            continue;
+        }
+        if toolchain.dir.exists() {
+            fs::remove_dir_all(&toolchain.dir)
+                .with_context(|| format!("failed to remove `{}`", toolchain.dir.display()))?;
+        }
+        fs::create_dir_all(&toolchain.dir)?;
+        fs::rename(&legacy, toolchain.dir.join(&toolchain.name)).with_context(|| {
+            format!(
+                "failed to move `{}` to `{}`",
+                legacy.display(),
+                toolchain.dir.display()
+            )
+        })?;
+        eprintln!(
+            "Note: moved patched Go `{version}` from `{}` to `{}`.",
+            dir.display(),
+            toolchain.dir.display()
+        );
+    }
+    Ok(())
+}
+
+/// The directory containing [`LEGACY_DIRS`].
+fn legacy_base() -> Result<PathBuf> {
+    Ok(root()?.parent().map(Path::to_path_buf).unwrap_or_default())
+}
+
+/// Take the lock which earlier versions of `componentize-go` held while
+/// downloading into the legacy directory `dir`, or return `None` if one
+/// still holds it.
+fn try_lock_legacy(dir: &Path) -> Result<Option<File>> {
+    let path = dir.join(LEGACY_LOCK);
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("failed to open `{}`", path.display()))?;
+    match file.try_lock() {
+        Ok(()) => Ok(Some(file)),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(e)) => {
+            Err(e).with_context(|| format!("failed to lock `{}`", path.display()))
+        }
+    }
+}
+
+/// The directory in which patched Go releases are stored, one subdirectory
+/// per version.
+pub fn root() -> Result<PathBuf> {
+    let Some(cache_dir) = dirs::cache_dir() else {
+        bail!("unable to determine cache directory for current user");
+    };
+    Ok(cache_dir.join("componentize-go").join("toolchains"))
+}
+
+/// The lock files of any toolchains which are being, or have been, installed.
+pub fn lock_files() -> Result<Vec<PathBuf>> {
+    let mut files = fs::read_dir(root()?)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == LOCK_EXTENSION))
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+/// Download `url`, reporting its size and, if stderr is a terminal, progress.
+fn download(url: &str) -> Result<Vec<u8>> {
+    let mut response = reqwest::blocking::get(url)?.error_for_status()?;
+    let total = response.content_length();
+
+    match total {
+        Some(total) => eprintln!(
+            "Downloading patched Go from {url} ({}).",
+            format_size(total)
+        ),
+        None => eprintln!("Downloading patched Go from {url}."),
+    }
+
+    let progress = std::io::stderr().is_terminal();
+    let mut content = Vec::with_capacity(total.unwrap_or(0).try_into().unwrap_or(0));
+    let mut buffer = vec![0; 64 * 1024];
+    let mut last_report = Instant::now();
+    loop {
+        let count = response
+            .read(&mut buffer)
+            .with_context(|| format!("failed to download {url}"))?;
+        if count == 0 {
+            break;
+        }
+        content.extend_from_slice(&buffer[..count]);
+
+        if progress && last_report.elapsed() >= Duration::from_millis(100) {
+            eprint!("\r{}", progress_line(content.len() as u64, total));
+            last_report = Instant::now();
+        }
+    }
+
+    if progress {
+        eprintln!("\r{}", progress_line(content.len() as u64, total));
+    } else {
+        eprintln!("Downloaded {}.", format_size(content.len() as u64));
+    }
+
+    if let Some(total) = total
+        && content.len() as u64 != total
+    {
+        bail!(
+            "download of {url} was truncated: expected {}, received {}",
+            format_size(total),
+            format_size(content.len() as u64)
+        );
+    }
+    Ok(content)
+}
+
+/// A line describing how much of a download has been received.
+fn progress_line(received: u64, total: Option<u64>) -> String {
+    match total {
+        Some(total) if total > 0 => format!(
+            "  {} / {} ({}%)",
+            format_size(received),
+            format_size(total),
+            received * 100 / total
+        ),
+        _ => format!("  {}", format_size(received)),
+    }
+}
+
+/// A human-readable size, e.g. `68.3 MiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// The pinned SHA-256 digest of the archive for the given version and
+/// distribution name, if any.
+fn checksum(version: &str, name: &str) -> Option<&'static str> {
+    CHECKSUMS
+        .iter()
+        .find(|(v, n, _)| *v == version && *n == name)
+        .map(|(_, _, digest)| *digest)
+}
+
+/// The lower-case hexadecimal SHA-256 digest of `bytes`.
+pub fn sha256(bytes: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, bytes)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// The current platform's operating system and architecture, using Go's
+/// naming conventions.
+fn platform() -> (&'static str, &'static str) {
+    let os = match std::env::consts::OS {
+        "macos" => "darwin",
+        os => os,
+    };
+
+    let arch = match std::env::consts::ARCH {
+        "aarch64" => "arm64",
+        "x86_64" => "amd64",
+        "x86" => "386",
+        "loongarch64" => "loong64",
+        "powerpc64" if cfg!(target_endian = "little") => "ppc64le",
+        "powerpc64" => "ppc64",
+        arch => arch,
+    };
+
+    (os, arch)
+}
+
+/// The name of the patched Go distribution for the current platform.
+fn distribution_name() -> String {
+    let (os, arch) = platform();
+    format!("go-{os}-{arch}-bootstrap")
+}
+
+/// Fail unless patched Go releases are published for the current platform.
+fn check_release_platform() -> Result<()> {
+    let (os, arch) = platform();
+    if RELEASE_PLATFORMS.contains(&(os, arch)) {
+        return Ok(());
+    }
+    bail!(
+        "no patched Go release is available for {os}/{arch}; releases are only published for {}.\n\
+         Either build the patched Go from https://github.com/dicej/go yourself and pass its \
+         `bin/go` via `--go`, or have componentize-go build it with \
+         `componentize-go toolchain install --source <DIR>`.",
+        RELEASE_PLATFORMS
+            .iter()
+            .map(|(os, arch)| format!("{os}/{arch}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+/// Copy the directory `from`, recursively, to `to`, which must not exist.
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in
+        fs::read_dir(from).with_context(|| format!("failed to read `{}`", from.display()))?
+    {
+        let entry = entry?;
+        let ty = entry.file_type()?;
+        let dest = to.join(entry.file_name());
+        if ty.is_dir() {
+            copy_dir(&entry.path(), &dest)?;
+        } else if ty.is_symlink() {
+            let target = fs::read_link(entry.path())?;
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(target, &dest)?;
+            #[cfg(not(unix))]
+            fs::copy(from.join(target), &dest)?;
+        } else {
+            fs::copy(entry.path(), &dest)
+                .with_context(|| format!("failed to copy `{}`", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            sha256(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let toolchain = Toolchain::new(DEFAULT_VERSION).unwrap();
+        let digest = sha256(b"archive");
+        toolchain.verify_digest(b"archive", Some(&digest)).unwrap();
+        let error = toolchain
+            .verify_digest(b"tampered", Some(&digest))
+            .unwrap_err();
+        assert!(error.to_string().contains("SHA-256 mismatch"));
+
+        // Archives of known versions must have a pinned digest; others need not.
+        assert!(toolchain.verify_digest(b"archive", None).is_err());
+        let toolchain = Toolchain::new("go1.26.0-custom").unwrap();
+        toolchain.verify_digest(b"archive", None).unwrap();
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(68 * 1024 * 1024 + 300 * 1024), "68.3 MiB");
+        assert_eq!(progress_line(512, Some(1024)), "  512 B / 1.0 KiB (50%)");
+        assert_eq!(progress_line(512, None), "  512 B");
+    }
+}
@@ -1,17 +1,23 @@
+use crate::{
+    command::{Adapter, AsyncMode, WitOpts},
+    config::{self, Origin, WitConfig, WitSource},
+    gomod::{self, GoVersion},
+    packages::load_packages,
+    toolchain::{InstallOptions, Toolchain},
+};
 use anyhow::{Context, Result, anyhow, bail};
-use bzip2::read::BzDecoder;
-use serde::Deserialize;
 use std::{
     collections::BTreeSet,
-    fs::{self, File},
-    io::Cursor,
+    env,
+    ffi::OsString,
+    fmt, fs, iter,
     path::{Path, PathBuf},
     process::Command,
 };
-use tar::Archive;
+use wasmparser::{Encoding, Payload};
 use wit_parser::{
-    CloneMaps, Function, Interface, Package, PackageName, Resolve, Stability, Type, TypeDef,
-    TypeDefKind, World, WorldId, WorldItem,
+    CloneMaps, Function, Handle, Package, PackageName, Resolve, Stability, Type, TypeDefKind,
+    World, WorldId, WorldItem, WorldKey,
 };
 
 pub fn dummy_wit() -> (Resolve, WorldId) {
@@ -31,30 +37,53 @@ pub fn dummy_wit() -> (Resolve, WorldId) {
 
 // In the rare case the snapshot needs to be updated, the latest version
 // can be found here: https://github.com/bytecodealliance/wasmtime/releases
-const WASIP1_SNAPSHOT_ADAPT: &[u8] = include_bytes!("wasi_snapshot_preview1.reactor.wasm");
+//
+// The reactor adapter imports WASI 0.2.6.  TODO: the command and proxy
+// adapters are from Wasmtime 29.0.1, and import WASI 0.2.3; replace them with
+// those of the reactor's release, and update all three together from then on.
+pub const WASIP1_SNAPSHOT_ADAPT: &[u8] = include_bytes!("wasi_snapshot_preview1.reactor.wasm");
+pub const WASIP1_SNAPSHOT_ADAPT_COMMAND: &[u8] =
+    include_bytes!("wasi_snapshot_preview1.command.wasm");
+pub const WASIP1_SNAPSHOT_ADAPT_PROXY: &[u8] = include_bytes!("wasi_snapshot_preview1.proxy.wasm");
+
+/// Parse the WIT documents and select the world specified by `wit_opts`.
+///
+/// `go_path` is the Go binary specified on the command line, if any, which is
+/// used when scanning the current module's dependencies for
+/// `componentize-go.toml` files.
+pub fn parse_wit(wit_opts: &WitOpts, go_path: Option<&Path>) -> Result<(Resolve, WorldId)> {
+    parse_wit_with_config(wit_opts, go_path).map(|(resolve, world, _)| (resolve, world))
+}
 
-pub fn parse_wit(
-    paths: &[impl AsRef<Path>],
-    worlds: &[String],
-    ignore_toml_files: bool,
-    features: &[String],
-    all_features: bool,
-) -> Result<(Resolve, WorldId)> {
-    let (paths, worlds) = &maybe_add_dependencies(paths, worlds, ignore_toml_files)?;
+/// Like [`parse_wit`], but also return the configuration the world was
+/// chosen with.
+pub fn parse_wit_with_config(
+    wit_opts: &WitOpts,
+    go_path: Option<&Path>,
+) -> Result<(Resolve, WorldId, WitConfig)> {
+    parse_wit_from_config(wit_opts, config::discover(wit_opts, go_path)?)
+}
 
+/// Like [`parse_wit_with_config`], but using a configuration already found by
+/// [`config::discover`].
+pub fn parse_wit_from_config(
+    wit_opts: &WitOpts,
+    mut config: WitConfig,
+) -> Result<(Resolve, WorldId, WitConfig)> {
     // If no WIT directory was provided as a parameter and none were referenced
     // by Go packages, use ./wit by default.
-    if paths.is_empty() {
-        let paths = &[Path::new("wit")];
-        return parse_wit(paths, worlds, ignore_toml_files, features, all_features);
+    if config.sources.is_empty() {
+        config.sources.push(WitSource {
+            path: PathBuf::from("wit"),
+            origin: Origin::CommandLine,
+        });
     }
-    debug_assert!(!paths.is_empty(), "The paths should not be empty");
 
     let mut resolve = Resolve {
-        all_features,
+        all_features: wit_opts.all_features,
         ..Default::default()
     };
-    for features in features {
+    for features in &wit_opts.features {
         for feature in features
             .split(',')
             .flat_map(|s| s.split_whitespace())
@@ -64,27 +93,17 @@ pub fn parse_wit(
         }
     }
 
-    let packages = paths
-        .iter()
-        .map(|path| {
-            // Consolidates if the same package is referenced in multiple worlds
-            let mut tmp = Resolve {
-                all_features,
-                features: resolve.features.clone(),
-                ..Default::default()
-            };
-            let (pkg, _files) = tmp.push_path(path)?;
-            let consolidated = resolve.merge(tmp)?;
-            Ok(consolidated.packages[pkg.index()])
-        })
-        .collect::<Result<Vec<_>>>()?;
+    let packages = load_packages(&mut resolve, &config)?;
 
-    let worlds = worlds
+    let worlds = config
+        .worlds
         .iter()
         .map(|world| {
+            let name = &packages.world_name(world);
             packages
+                .mains
                 .iter()
-                .find_map(|&pkg| resolve.select_world(&[pkg], Some(world)).ok())
+                .find_map(|&pkg| resolve.select_world(&[pkg], Some(name)).ok())
                 .ok_or_else(|| {
                     anyhow!("no world named `{world}` found in any of the loaded WIT packages")
                 })
@@ -93,6 +112,7 @@ pub fn parse_wit(
 
     let world = match &worlds[..] {
         [] => packages
+            .mains
             .iter()
             .find_map(|&pkg| resolve.select_world(&[pkg], None).ok())
             .ok_or_else(|| anyhow!("no default world found in any of the loaded WIT packages"))?,
@@ -132,59 +152,7 @@ pub fn parse_wit(
         }
     };
 
-    Ok((resolve, world))
-}
-
-/// Unless `ignore_toml_files` is `true`, use `go list` to search the current
-/// module and its dependencies for any `componentize-go.toml` files.  The WIT
-/// path and/or world specified in each such file will be added to the
-/// respective list and returned.
-fn maybe_add_dependencies(
-    paths: &[impl AsRef<Path>],
-    worlds: &[String],
-    ignore_toml_files: bool,
-) -> Result<(Vec<PathBuf>, Vec<String>)> {
-    let mut paths = paths
-        .iter()
-        .map(|v| PathBuf::from(v.as_ref()))
-        .collect::<BTreeSet<_>>();
-    let mut worlds = worlds.iter().cloned().collect::<BTreeSet<_>>();
-    // Only add worlds from `componentize-go.toml` files if none were specified
-    // explicitly via the CLI:
-    let add_worlds = worlds.is_empty();
-
-    if !ignore_toml_files && Path::new("go.mod").exists() {
-        let output = Command::new("go")
-            .args(["list", "-mod=readonly", "-m", "-f", "{{.Dir}}", "all"])
-            .output()?;
-        if !output.status.success() {
-            bail!(
-                "`go list` failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            );
-        }
-
-        #[derive(Deserialize)]
-        struct ComponentizeGoConfig {
-            #[serde(default)]
-            worlds: Vec<String>,
-            #[serde(default)]
-            wit_paths: Vec<String>,
-        }
-
-        for module in String::from_utf8(output.stdout)?.lines() {
-            let module = PathBuf::from(module);
-            if let Ok(manifest) = fs::read_to_string(module.join("componentize-go.toml")) {
-                let config = toml::from_str::<ComponentizeGoConfig>(&manifest)?;
-                if add_worlds {
-                    worlds.extend(config.worlds);
-                }
-                paths.extend(config.wit_paths.into_iter().map(|v| module.join(v)));
-            }
-        }
-    }
-
-    Ok((paths.into_iter().collect(), worlds.into_iter().collect()))
+    Ok((resolve, world, config))
 }
 
 // Converts a relative path to an absolute path.
@@ -208,18 +176,221 @@ pub fn embed_wit(wasm_file: &Path, resolve: &Resolve, world: WorldId) -> Result<
     Ok(())
 }
 
-/// Update the wasm module to use the current component model ABI.
-pub fn module_to_component(wasm_file: &Path, adapt_file: Option<&Path>) -> Result<()> {
+/// Choose the adapter for the specified world: `proxy` if it exports
+/// `wasi:http/incoming-handler`, and `reactor` otherwise.
+///
+/// The `command` adapter is never chosen, even for a world exporting only
+/// `wasi:cli/run`: the adapter exports `wasi:cli/run` itself, which conflicts
+/// with the bindings' export of it.  It is only used by `build --command`.
+pub fn select_adapter(resolve: &Resolve, world: WorldId) -> Adapter {
+    let exports = resolve.worlds[world]
+        .exports
+        .keys()
+        .map(|key| match key {
+            WorldKey::Interface(id) => {
+                let name = resolve.id_of(*id).unwrap_or_default();
+                match name.split_once('@') {
+                    Some((name, _)) => name.to_string(),
+                    None => name,
+                }
+            }
+            WorldKey::Name(name) => name.clone(),
+        })
+        .collect::<Vec<_>>();
+
+    if exports
+        .iter()
+        .any(|name| name == "wasi:http/incoming-handler")
+    {
+        Adapter::Proxy
+    } else {
+        Adapter::Reactor
+    }
+}
+
+/// The embedded snapshot adapter of the specified kind.
+fn adapter_bytes(adapter: Adapter) -> &'static [u8] {
+    match adapter {
+        Adapter::Reactor => WASIP1_SNAPSHOT_ADAPT,
+        Adapter::Command => WASIP1_SNAPSHOT_ADAPT_COMMAND,
+        Adapter::Proxy => WASIP1_SNAPSHOT_ADAPT_PROXY,
+    }
+}
+
+/// The WASI interfaces imported and exported by a snapshot adapter.
+pub struct AdapterInterfaces {
+    /// The imported interfaces, e.g. `wasi:cli/environment@0.2.6`.
+    pub imports: BTreeSet<String>,
+    /// The exported interfaces, e.g. `wasi:cli/run@0.2.6`.
+    pub exports: BTreeSet<String>,
+}
+
+impl AdapterInterfaces {
+    /// The versions of WASI the adapter imports.
+    pub fn versions(&self) -> BTreeSet<semver::Version> {
+        self.imports
+            .iter()
+            .filter_map(|name| name.split_once('@'))
+            .filter_map(|(_, version)| semver::Version::parse(version).ok())
+            .collect()
+    }
+}
+
+/// Read the WASI interfaces imported and exported by the snapshot adapter in
+/// `adapter`, failing if it is not a core module.
+pub fn adapter_interfaces(adapter: &[u8]) -> Result<AdapterInterfaces> {
+    let mut imports = BTreeSet::new();
+    let mut exports = BTreeSet::new();
+    for payload in wasmparser::Parser::new(0).parse_all(adapter) {
+        match payload? {
+            Payload::Version {
+                encoding: Encoding::Component,
+                ..
+            } => bail!("expected a core module, found a component"),
+            Payload::ImportSection(reader) => {
+                for import in reader.into_imports() {
+                    let import = import?;
+                    if import.module.starts_with("wasi:") {
+                        imports.insert(import.module.to_string());
+                    }
+                }
+            }
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    if let Some((interface, _)) = export?.name.split_once('#')
+                        && interface.starts_with("wasi:")
+                    {
+                        exports.insert(interface.to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(AdapterInterfaces { imports, exports })
+}
+
+/// Check that the snapshot adapter described by `name` is compatible with the
+/// target world, so that a mismatch is reported clearly rather than as an
+/// encoding failure or a component which fails at runtime.
+///
+/// Worlds may import a different WASI release than the adapter, e.g. WASI 0.3
+/// alongside the adapter's WASI 0.2, but not a semver-incompatible release of
+/// the same one, such as a 0.2.0 release candidate.  The adapter may also not
+/// export an interface which the world exports.
+fn check_adapter(adapter: &[u8], name: &str, resolve: &Resolve, world: WorldId) -> Result<()> {
+    let interfaces =
+        adapter_interfaces(adapter).with_context(|| format!("{name} is not a valid adapter"))?;
+    let versions = interfaces.versions();
+    if versions.is_empty() {
+        bail!(
+            "{name} does not import any versioned WASI interfaces, so it is not a WASI \
+             Preview 1 adapter"
+        );
+    }
+    let adapter_versions = versions
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let world_name = world_name(resolve, world);
+
+    for (key, item) in &resolve.worlds[world].imports {
+        let (WorldKey::Interface(id), WorldItem::Interface { .. }) = (key, item) else {
+            continue;
+        };
+        let Some(package) = resolve.interfaces[*id].package else {
+            continue;
+        };
+        let package_name = &resolve.packages[package].name;
+        let Some(version) = &package_name.version else {
+            continue;
+        };
+        if package_name.namespace != "wasi" {
+            continue;
+        }
+
+        let track = PackageName::version_compat_track(version);
+        let same_release =
+            |other: &semver::Version| other.major == version.major && other.minor == version.minor;
+        if versions.iter().any(same_release)
+            && !versions
+                .iter()
+                .any(|other| PackageName::version_compat_track(other) == track)
+        {
+            bail!(
+                "{name} implements WASI {adapter_versions}, but the target world `{world_name}` \
+                 imports `{}` from the incompatible WASI {version}; use an adapter from a \
+                 Wasmtime release which implements WASI {version} with `--adapt <FILE>`, or \
+                 target WASI {adapter_versions} instead",
+                resolve.id_of(*id).unwrap_or_default()
+            );
+        }
+    }
+
+    let unversioned = |name: &str| name.split('@').next().unwrap_or_default().to_string();
+    let adapter_exports = interfaces
+        .exports
+        .iter()
+        .map(|name| unversioned(name))
+        .collect::<BTreeSet<_>>();
+    for key in resolve.worlds[world].exports.keys() {
+        if let WorldKey::Interface(id) = key
+            && let Some(export) = resolve.id_of(*id)
+            && adapter_exports.contains(&unversioned(&export))
+        {
+            bail!(
+                "{name} exports `{}`, which the target world `{world_name}` also exports; \
+                 use the reactor adapter with `--adapter reactor` instead",
+                unversioned(&export)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// The fully qualified name of a world, e.g. `wasi:http/proxy@0.2.0`.
+pub fn world_name(resolve: &Resolve, world: WorldId) -> String {
+    let item = &resolve.worlds[world];
+    match item.package {
+        Some(package) => resolve.id_of_name(package, &item.name),
+        None => item.name.clone(),
+    }
+}
+
+/// Update the wasm module to use the current component model ABI, using the
+/// adapter in `adapt_file` if specified, or else the embedded one of the
+/// specified kind, after checking that the adapter suits the target world.
+///
+/// Unless `validate` is `false`, e.g. because the caller validates the
+/// component itself with [`crate::features::validate_features`], the encoder
+/// validates it with every wasm feature enabled.
+pub fn module_to_component(
+    wasm_file: &Path,
+    resolve: &Resolve,
+    world: WorldId,
+    adapt_file: Option<&Path>,
+    adapter: Adapter,
+    validate: bool,
+) -> Result<()> {
     let wasm: Vec<u8> = fs::read(wasm_file)?;
 
-    let mut encoder = wit_component::ComponentEncoder::default().validate(true);
+    let mut encoder = wit_component::ComponentEncoder::default().validate(validate);
     encoder = encoder.module(&wasm)?;
-    let adapt_bytes = if let Some(adapt) = adapt_file {
-        fs::read(adapt)
-            .with_context(|| format!("failed to read adapt file '{}'", adapt.display()))?
+    let (adapt_bytes, adapt_name) = if let Some(adapt) = adapt_file {
+        (
+            fs::read(adapt)
+                .with_context(|| format!("failed to read adapt file '{}'", adapt.display()))?,
+            format!("adapter `{}`", adapt.display()),
+        )
     } else {
-        WASIP1_SNAPSHOT_ADAPT.to_vec()
+        (
+            adapter_bytes(adapter).to_vec(),
+            format!("the embedded {} adapter", adapter.name()),
+        )
     };
+    check_adapter(&adapt_bytes, &adapt_name, resolve, world)?;
     encoder = encoder.adapter("wasi_snapshot_preview1", &adapt_bytes)?;
 
     let bytes = encoder
@@ -231,88 +402,176 @@ pub fn module_to_component(wasm_file: &Path, adapt_file: Option<&Path>) -> Resul
     Ok(())
 }
 
-/// Ensure that the Go version is compatible with the embedded Wasm tooling.
+/// Ensure that the Go version is compatible with the embedded Wasm tooling and
+/// with the current module's `go` directive.
+///
+/// The version checked is that of the toolchain `go_path` actually runs in the
+/// current directory, i.e. after any switching due to `GOTOOLCHAIN` or a
+/// `toolchain` directive.
 pub fn check_go_version(go_path: &Path) -> Result<()> {
-    let output = Command::new(go_path).arg("version").output()?;
+    let version = go_version(go_path)?;
+
+    // TODO: there might be a patch number correlated with wasip3.
+    if !(version.major == 1 && version.minor >= 25) {
+        bail!("Go version is not valid. Expected '^1.25.0', found '{version}'");
+    }
+
+    if let Some(requirement) = gomod::requirement()?
+        && let Some(go) = requirement.go
+        && version < go
+    {
+        let hint = if gomod::go_env("GOTOOLCHAIN").as_deref() == Some("local") {
+            "; `GOTOOLCHAIN=local` prevents switching to a newer toolchain"
+        } else {
+            ""
+        };
+        bail!(
+            "`{}` requires {go} or later, but {} runs {version}{hint}",
+            requirement.file.display(),
+            go_path.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Ask `go` for the version of the toolchain it actually runs in the current
+/// directory.
+pub fn go_version(go_path: &Path) -> Result<GoVersion> {
+    let output = Command::new(go_path)
+        .args(["env", "GOVERSION"])
+        .output()
+        .with_context(|| format!("failed to run `{} env GOVERSION`", go_path.display()))?;
 
     if !output.status.success() {
         return Err(anyhow!(
-            "'go version' command failed: {}",
+            "'go env GOVERSION' command failed: {}",
             String::from_utf8_lossy(&output.stderr)
         ));
     }
 
     let version_string = String::from_utf8(output.stdout)?;
-    let version_regex = regex::Regex::new(r"go(\d+)\.(\d+)\.(\d+)").unwrap();
-    let semver = version_regex.captures(&version_string).map(|caps| {
-        (
-            caps[1].parse::<u32>().unwrap(), // Major
-            caps[2].parse::<u32>().unwrap(), // Minor
-            caps[3].parse::<u32>().unwrap(), // Patch
-        )
-    });
+    GoVersion::parse(version_string.trim())
+        .ok_or_else(|| anyhow!("Failed to parse Go version from: {version_string}"))
+}
 
-    if let Some((major, minor, patch)) = semver {
-        // TODO: there might be a patch number correlated with wasip3.
-        if major == 1 && minor >= 25 {
-            Ok(())
-        } else {
-            Err(anyhow!(
-                "Go version is not valid. Expected '^1.25.0', found '{}.{}.{}'",
-                major,
-                minor,
-                patch
-            ))
+/// Ensure that building with the patched Go `toolchain` would honour
+/// `GOTOOLCHAIN` and the current module's `go` and `toolchain` directives,
+/// rather than silently using an older Go than requested.
+fn check_patched_go_version(toolchain: &Toolchain) -> Result<()> {
+    let Some(provided) = GoVersion::parse(&toolchain.version) else {
+        // Not a conventionally named release; `check_go_version` will catch
+        // any problems once it is installed.
+        return Ok(());
+    };
+
+    if let Some(name) = gomod::forced_toolchain()
+        && GoVersion::parse(&name) != Some(provided)
+    {
+        bail!(
+            "`GOTOOLCHAIN` selects `{name}`, which would replace the patched Go `{}` \
+             ({provided}); unset `GOTOOLCHAIN` or set it to `local`, or use `--go` to \
+             specify a patched {name}",
+            toolchain.version
+        );
+    }
+
+    if let Some(requirement) = gomod::requirement()? {
+        for (directive, version) in [("go", requirement.go), ("toolchain", requirement.toolchain)] {
+            if let Some(version) = version
+                && version > provided
+            {
+                bail!(
+                    "`{}` asks for `{directive} {version}`, but the patched Go `{}` is only \
+                     {provided}; use `--go` to specify a patched Go of at least {version}, or \
+                     select a newer patched release with the `toolchain` field of \
+                     `componentize-go.toml`",
+                    requirement.file.display(),
+                    toolchain.version
+                );
+            }
         }
-    } else {
-        Err(anyhow!(
-            "Failed to parse Go version from: {}",
-            version_string
-        ))
     }
+
+    Ok(())
+}
+
+/// Ensure that the Go toolchain includes [this
+/// patch](https://github.com/golang/go/pull/76775), which is required for
+/// async support, returning the reason if not.
+///
+/// The toolchain itself is asked for its `GOROOT` so that symlinked installs,
+/// version manager shims, and `GOTOOLCHAIN` switching are all handled.
+pub fn check_go_async_support(go: &Path) -> Result<()> {
+    let goroot = go_root(go)?;
+    let lock = goroot.join("src").join("runtime").join("lock_wasip1.go");
+    let source = fs::read_to_string(&lock).with_context(|| {
+        format!(
+            "unable to read the runtime source of the Go at `{}`",
+            goroot.display()
+        )
+    })?;
+    if !source.contains("wasiOnIdle") {
+        bail!(
+            "the runtime in `{}` lacks the `wasiOnIdle` hook",
+            goroot.display()
+        );
+    }
+    Ok(())
+}
+
+/// Ask `go` for the `GOROOT` of the toolchain it actually runs in the current
+/// directory.
+pub fn go_root(go: &Path) -> Result<PathBuf> {
+    let output = Command::new(go)
+        .args(["env", "GOROOT"])
+        .output()
+        .with_context(|| format!("failed to run `{} env GOROOT`", go.display()))?;
+    if !output.status.success() {
+        bail!(
+            "`go env GOROOT` failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(PathBuf::from(String::from_utf8(output.stdout)?.trim()))
 }
 
-fn check_go_async_support(go: &Path) -> Option<()> {
-    fs::read_to_string(
-        go.parent()?
-            .parent()?
-            .join("src")
-            .join("runtime")
-            .join("lock_wasip1.go"),
-    )
-    .ok()?
-    .contains("wasiOnIdle")
-    .then_some(())
+/// The environment variables with which to run `go` so that it targets
+/// `wasip1` using the toolchain at `go` (and its companion tools).
+pub fn go_env_vars(go: &Path) -> Result<Vec<(&'static str, OsString)>> {
+    let goroot = go_root(go)?;
+    let bin = goroot.join("bin");
+    let path = env::var_os("PATH").unwrap_or_default();
+    let path = env::join_paths(
+        iter::once(bin.clone()).chain(env::split_paths(&path).filter(|dir| *dir != bin)),
+    )?;
+    Ok(vec![
+        ("GOROOT", goroot.into_os_string()),
+        ("PATH", path),
+        ("GOOS", "wasip1".into()),
+        ("GOARCH", "wasm".into()),
+    ])
 }
 
-fn world_needs_async(resolve: &Resolve, world: WorldId) -> bool {
-    fn typedef_needs_async(resolve: &Resolve, ty: &TypeDef) -> bool {
-        match &ty.kind {
-            TypeDefKind::Record(v) => v.fields.iter().any(|v| type_needs_async(resolve, v.ty)),
-            TypeDefKind::Tuple(v) => v.types.iter().any(|&v| type_needs_async(resolve, v)),
-            TypeDefKind::Variant(v) => v
-                .cases
-                .iter()
-                .any(|v| v.ty.map(|v| type_needs_async(resolve, v)).unwrap_or(false)),
-            &TypeDefKind::Type(v)
-            | &TypeDefKind::Option(v)
-            | &TypeDefKind::List(v)
-            | &TypeDefKind::FixedLengthList(v, _) => type_needs_async(resolve, v),
-            TypeDefKind::Result(v) => {
-                v.ok.map(|v| type_needs_async(resolve, v)).unwrap_or(false)
-                    || v.err.map(|v| type_needs_async(resolve, v)).unwrap_or(false)
-            }
-            &TypeDefKind::Map(k, v) => type_needs_async(resolve, k) || type_needs_async(resolve, v),
-            TypeDefKind::Future(_) | TypeDefKind::Stream(_) => true,
-            TypeDefKind::Resource
-            | TypeDefKind::Handle(_)
-            | TypeDefKind::Flags(_)
-            | TypeDefKind::Enum(_) => false,
-            TypeDefKind::Unknown => unreachable!(),
-        }
+/// A WIT item which requires a Go toolchain with async support.
+pub struct AsyncUse {
+    /// The path of the item, e.g. `wasi:http/handler@0.3.0#handle`.
+    pub item: String,
+    /// Why the item needs async, e.g. `parameter `body` uses `stream<u8>``.
+    pub reason: String,
+}
+
+impl fmt::Display for AsyncUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.item, self.reason)
     }
+}
 
-    fn type_needs_async(resolve: &Resolve, ty: Type) -> bool {
+/// List the functions and types imported or exported by the specified world
+/// which require async support.
+pub fn world_needs_async(resolve: &Resolve, world: WorldId) -> Vec<AsyncUse> {
+    /// Return the first `future` or `stream` type found in `ty`, if any.
+    fn type_needs_async(resolve: &Resolve, ty: Type) -> Option<Type> {
         match ty {
             Type::Bool
             | Type::U8
@@ -327,71 +586,247 @@ fn world_needs_async(resolve: &Resolve, world: WorldId) -> bool {
             | Type::F64
             | Type::Char
             | Type::String
-            | Type::ErrorContext => false,
-            Type::Id(id) => typedef_needs_async(resolve, &resolve.types[id]),
+            | Type::ErrorContext => None,
+            Type::Id(id) => match &resolve.types[id].kind {
+                TypeDefKind::Record(v) => v
+                    .fields
+                    .iter()
+                    .find_map(|v| type_needs_async(resolve, v.ty)),
+                TypeDefKind::Tuple(v) => v.types.iter().find_map(|&v| type_needs_async(resolve, v)),
+                TypeDefKind::Variant(v) => v
+                    .cases
+                    .iter()
+                    .find_map(|v| v.ty.and_then(|v| type_needs_async(resolve, v))),
+                &TypeDefKind::Type(v)
+                | &TypeDefKind::Option(v)
+                | &TypeDefKind::List(v)
+                | &TypeDefKind::FixedLengthList(v, _) => type_needs_async(resolve, v),
+                TypeDefKind::Result(v) => {
+                    v.ok.and_then(|v| type_needs_async(resolve, v))
+                        .or_else(|| v.err.and_then(|v| type_needs_async(resolve, v)))
+                }
+                &TypeDefKind::Map(k, v) => {
+                    type_needs_async(resolve, k).or_else(|| type_needs_async(resolve, v))
+                }
+                TypeDefKind::Future(_) | TypeDefKind::Stream(_) => Some(ty),
+                TypeDefKind::Resource
+                | TypeDefKind::Handle(_)
+                | TypeDefKind::Flags(_)
+                | TypeDefKind::Enum(_) => None,
+                TypeDefKind::Unknown => unreachable!(),
+            },
         }
     }
 
     let function_needs_async = |fun: &Function| {
-        fun.kind.is_async()
-            || fun.params.iter().any(|v| type_needs_async(resolve, v.ty))
-            || fun
-                .result
-                .map(|ty| type_needs_async(resolve, ty))
-                .unwrap_or(false)
+        if fun.kind.is_async() {
+            return Some("async function".to_string());
+        }
+        fun.params
+            .iter()
+            .find_map(|v| {
+                type_needs_async(resolve, v.ty)
+                    .map(|ty| format!("parameter `{}` uses `{}`", v.name, type_name(resolve, ty)))
+            })
+            .or_else(|| {
+                fun.result
+                    .and_then(|ty| type_needs_async(resolve, ty))
+                    .map(|ty| format!("result uses `{}`", type_name(resolve, ty)))
+            })
     };
 
-    let interface_needs_async = |interface: &Interface| {
-        interface
-            .types
-            .values()
-            .any(|&id| type_needs_async(resolve, Type::Id(id)))
-            || interface.functions.values().any(function_needs_async)
+    let type_use = |item: String, id| {
+        type_needs_async(resolve, Type::Id(id)).map(|ty| AsyncUse {
+            item,
+            reason: format!("type uses `{}`", type_name(resolve, ty)),
+        })
     };
 
     let world = &resolve.worlds[world];
-    world
-        .imports
-        .values()
-        .chain(world.exports.values())
-        .any(|item| {
-            match item {
-                &WorldItem::Interface { id, .. } => {
-                    if interface_needs_async(&resolve.interfaces[id]) {
-                        return true;
-                    }
-                }
-                WorldItem::Function(fun) => {
-                    if function_needs_async(fun) {
-                        return true;
-                    }
-                }
-                &WorldItem::Type { id, .. } => {
-                    if type_needs_async(resolve, Type::Id(id)) {
-                        return true;
-                    }
-                }
+    let world_path = match world.package {
+        Some(package) => resolve.id_of_name(package, &world.name),
+        None => world.name.clone(),
+    };
+
+    let mut uses = Vec::new();
+    for (key, item) in world.imports.iter().chain(&world.exports) {
+        match item {
+            &WorldItem::Interface { id, .. } => {
+                let interface = &resolve.interfaces[id];
+                let path = resolve
+                    .id_of(id)
+                    .unwrap_or_else(|| resolve.name_world_key(key));
+                uses.extend(
+                    interface
+                        .types
+                        .iter()
+                        .filter_map(|(name, &id)| type_use(format!("{path}#{name}"), id)),
+                );
+                uses.extend(interface.functions.values().filter_map(|fun| {
+                    function_needs_async(fun).map(|reason| AsyncUse {
+                        item: format!("{path}#{}", fun.name),
+                        reason,
+                    })
+                }));
             }
-            false
-        })
+            WorldItem::Function(fun) => {
+                uses.extend(function_needs_async(fun).map(|reason| AsyncUse {
+                    item: format!("{world_path}#{}", fun.name),
+                    reason,
+                }));
+            }
+            &WorldItem::Type { id, .. } => {
+                let name = resolve.name_world_key(key);
+                uses.extend(type_use(format!("{world_path}#{name}"), id));
+            }
+        }
+    }
+    uses
 }
 
-pub fn pick_go(resolve: &Resolve, world: WorldId, go_path: Option<&Path>) -> Result<PathBuf> {
+/// Render a WIT type the way it would be written in a WIT file, e.g.
+/// `stream<u8>`.
+fn type_name(resolve: &Resolve, ty: Type) -> String {
+    let id = match ty {
+        Type::Bool => return "bool".into(),
+        Type::U8 => return "u8".into(),
+        Type::U16 => return "u16".into(),
+        Type::U32 => return "u32".into(),
+        Type::U64 => return "u64".into(),
+        Type::S8 => return "s8".into(),
+        Type::S16 => return "s16".into(),
+        Type::S32 => return "s32".into(),
+        Type::S64 => return "s64".into(),
+        Type::F32 => return "f32".into(),
+        Type::F64 => return "f64".into(),
+        Type::Char => return "char".into(),
+        Type::String => return "string".into(),
+        Type::ErrorContext => return "error-context".into(),
+        Type::Id(id) => id,
+    };
+
+    let ty = &resolve.types[id];
+    if let Some(name) = &ty.name {
+        return name.clone();
+    }
+    let name = |ty| type_name(resolve, ty);
+    let optional = |ty: Option<Type>| ty.map(name).unwrap_or_else(|| "_".into());
+    match &ty.kind {
+        &TypeDefKind::Type(ty) => name(ty),
+        &TypeDefKind::List(ty) => format!("list<{}>", name(ty)),
+        &TypeDefKind::FixedLengthList(ty, size) => format!("list<{}, {size}>", name(ty)),
+        &TypeDefKind::Option(ty) => format!("option<{}>", name(ty)),
+        &TypeDefKind::Map(k, v) => format!("map<{}, {}>", name(k), name(v)),
+        TypeDefKind::Result(r) => format!("result<{}, {}>", optional(r.ok), optional(r.err)),
+        TypeDefKind::Tuple(t) => format!(
+            "tuple<{}>",
+            t.types
+                .iter()
+                .map(|&ty| name(ty))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        &TypeDefKind::Future(ty) => match ty {
+            Some(ty) => format!("future<{}>", name(ty)),
+            None => "future".into(),
+        },
+        &TypeDefKind::Stream(ty) => match ty {
+            Some(ty) => format!("stream<{}>", name(ty)),
+            None => "stream".into(),
+        },
+        &TypeDefKind::Handle(Handle::Own(id)) => name(Type::Id(id)),
+        &TypeDefKind::Handle(Handle::Borrow(id)) => format!("borrow<{}>", name(Type::Id(id))),
+        _ => "<anonymous>".into(),
+    }
+}
+
+/// Find a Go binary with which to scan the current module's dependencies for
+/// `componentize-go.toml` files.
+///
+/// This happens before the target world is known, so it cannot take async
+/// support into account the way [`pick_go`] does.  Instead, it uses the
+/// specified binary, or the one in PATH, or else the selected patched Go if it
+/// has already been downloaded.  It never downloads anything, returning `None`
+/// if no Go is available yet.
+pub fn discovery_go(go_path: Option<&Path>) -> Result<Option<PathBuf>> {
+    if let Some(p) = go_path {
+        return Ok(Some(make_path_absolute(p)?));
+    }
+    if let Ok(go) = which::which("go") {
+        return Ok(Some(go));
+    }
+    let toolchain = Toolchain::selected()?;
+    Ok(toolchain.is_installed().then(|| toolchain.bin()))
+}
+
+/// Choose the Go binary with which to build for the specified world.
+///
+/// If `go_path` (or the `go` in PATH) is unsuitable, the patched Go selected
+/// for the current project is used instead, installing it as described by
+/// `install` if necessary.
+pub fn pick_go(
+    resolve: &Resolve,
+    world: WorldId,
+    go_path: Option<&Path>,
+    async_mode: AsyncMode,
+    verbose: bool,
+    install: &InstallOptions,
+) -> Result<PathBuf> {
+    let uses = world_needs_async(resolve, world);
+    let list = || {
+        uses.iter()
+            .map(|item| format!("\n  {item}"))
+            .collect::<String>()
+    };
+
+    if verbose && !uses.is_empty() {
+        eprintln!(
+            "Note: the following WIT items require async support:{}",
+            list()
+        );
+    }
+
+    let needs_async = match async_mode {
+        AsyncMode::Auto => !uses.is_empty(),
+        AsyncMode::Require => true,
+        AsyncMode::Forbid if uses.is_empty() => false,
+        AsyncMode::Forbid => bail!(
+            "the target world requires async support, which `--async=forbid` disallows:{}",
+            list()
+        ),
+    };
+    let why = match uses.as_slice() {
+        [] => "required by `--async=require`".to_string(),
+        [item] => format!("needed by `{}`", item.item),
+        [item, rest @ ..] if !verbose => format!(
+            "needed by `{}` and {} other item(s); use `--verbose` to list them",
+            item.item,
+            rest.len()
+        ),
+        [item, rest @ ..] => format!("needed by `{}` and {} other item(s)", item.item, rest.len()),
+    };
+
     let go = match go_path {
         Some(p) => Some(make_path_absolute(p)?),
         None => which::which("go").ok(),
     };
 
     if let Some(go) = go {
-        if world_needs_async(resolve, world) && check_go_async_support(&go).is_none() {
+        let async_support = if needs_async {
+            check_go_async_support(&go)
+        } else {
+            Ok(())
+        };
+        if let Err(e) = async_support {
             eprintln!(
-                "Note: {} does not support async operation; will use downloaded version.\n\
+                "Note: {} does not support async operation ({e:#}), {why}; \
+                 will use downloaded version.\n\
                  See https://github.com/golang/go/pull/76775 for details.",
                 go.display()
             )
-        } else if check_go_version(&go).is_err() {
+        } else if let Err(e) = check_go_version(&go) {
             eprintln!(
-                "Note: {} is not a compatible version of Go; will use downloaded version.",
+                "Note: {} is not a compatible version of Go ({e:#}); will use downloaded version.",
                 go.display()
             );
         } else {
@@ -401,54 +836,145 @@ pub fn pick_go(resolve: &Resolve, world: WorldId, go_path: Option<&Path>) -> Res
         eprintln!("Note: `go` command not found; will use downloaded version.");
     }
 
-    let Some(cache_dir) = dirs::cache_dir() else {
-        bail!("unable to determine cache directory for current user");
-    };
-
-    // Determine OS and architecture
-    let os = match std::env::consts::OS {
-        "macos" => "darwin",
-        "linux" => "linux",
-        "windows" => "windows",
-        bad_os => panic!("OS not supported: {bad_os}"),
-    };
-
-    // Map to Go's naming conventions
-    let arch = match std::env::consts::ARCH {
-        "aarch64" => "arm64",
-        "x86_64" => "amd64",
-        bad_arch => panic!("ARCH not supported: {bad_arch}"),
-    };
-
-    let cache_dir = &cache_dir.join("componentize-go").join("v2");
-    let name = &format!("go-{os}-{arch}-bootstrap");
-    let dir = cache_dir.join(name);
-    let bin = dir.join("bin").join("go");
+    let toolchain = Toolchain::selected()?;
+    check_patched_go_version(&toolchain)?;
+    toolchain.install(install)?;
+    let bin = toolchain.bin();
 
-    fs::create_dir_all(cache_dir)?;
-
-    // Grab a lock to avoid concurrent downloads
-    let lock_file = File::create(cache_dir.join("lock"))?;
-    lock_file.lock()?;
+    check_go_version(&bin)?;
+    check_go_async_support(&bin).context("downloaded Go does not support async")?;
 
-    if !bin.exists() {
-        let url = format!(
-            "https://github.com/dicej/go/releases/download/go1.25.5-wasi-on-idle-v2/{name}.tbz"
-        );
+    eprintln!("Using {}.", bin.display());
 
-        eprintln!("Downloading patched Go from {url}.");
+    Ok(bin)
+}
 
-        let content = reqwest::blocking::get(&url)?.error_for_status()?.bytes()?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn world(items: &str) -> (Resolve, WorldId) {
+        let mut resolve = Resolve::default();
+        let package = resolve
+            .push_str(
+                "test.wit",
+                &format!(
+                    "package test:test;\n\
+                     package wasi:cli@0.2.6 {{ interface run {{ run: func(); }} }}\n\
+                     package wasi:http@0.2.6 {{ interface incoming-handler {{ handle: func(); }} }}\n\
+                     interface other {{ f: func(); }}\n\
+                     world w {{ {items} }}"
+                ),
+            )
+            .unwrap();
+        let world = resolve.select_world(&[package], Some("w")).unwrap();
+        (resolve, world)
+    }
 
-        eprintln!("Extracting patched Go to {}.", cache_dir.display());
+    #[test]
+    fn test_select_adapter() {
+        for (items, adapter) in [
+            ("", Adapter::Reactor),
+            ("export other;", Adapter::Reactor),
+            ("export wasi:cli/run@0.2.6;", Adapter::Reactor),
+            ("export wasi:cli/run@0.2.6; export other;", Adapter::Reactor),
+            ("export wasi:http/incoming-handler@0.2.6;", Adapter::Proxy),
+        ] {
+            let (resolve, world) = world(items);
+            assert_eq!(select_adapter(&resolve, world), adapter, "{items}");
+        }
+    }
 
-        Archive::new(BzDecoder::new(Cursor::new(content))).unpack(cache_dir)?;
+    /// A core module with the shape of an adapter which imports `imports`,
+    /// e.g. `wasi:cli/environment@0.2.6#get-environment`.
+    fn adapter(imports: &[&str]) -> Vec<u8> {
+        use wasm_encoder::{EntityType, ImportSection, Module, TypeSection};
+
+        let mut module = Module::new();
+        let mut types = TypeSection::new();
+        types.ty().function([], []);
+        module.section(&types);
+        let mut section = ImportSection::new();
+        for import in imports {
+            let (interface, function) = import.split_once('#').unwrap();
+            section.import(interface, function, EntityType::Function(0));
+        }
+        module.section(&section);
+        module.finish()
     }
 
-    check_go_version(&bin)?;
-    check_go_async_support(&bin).ok_or_else(|| anyhow!("downloaded Go does not support async"))?;
+    #[test]
+    fn test_check_adapter() {
+        for (items, adapter, ok) in [
+            ("", WASIP1_SNAPSHOT_ADAPT_COMMAND, true),
+            ("export wasi:cli/run@0.2.6;", WASIP1_SNAPSHOT_ADAPT, true),
+            (
+                "export wasi:cli/run@0.2.6;",
+                WASIP1_SNAPSHOT_ADAPT_COMMAND,
+                false,
+            ),
+            (
+                "export wasi:http/incoming-handler@0.2.6;",
+                WASIP1_SNAPSHOT_ADAPT_PROXY,
+                true,
+            ),
+        ] {
+            let (resolve, world) = world(items);
+            match check_adapter(adapter, "adapter", &resolve, world) {
+                Ok(()) => assert!(ok, "{items}"),
+                Err(error) => {
+                    assert!(!ok, "{items}: {error}");
+                    assert!(error.to_string().contains("`wasi:cli/run`"), "{error}");
+                }
+            }
+        }
 
-    eprintln!("Using {}.", bin.display());
+        let (resolve, world) = dummy_wit();
+        let error = check_adapter(&adapter(&["env#f"]), "adapter", &resolve, world)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("not a WASI Preview 1 adapter"), "{error}");
+
+        let mut resolve = Resolve::default();
+        let package = resolve
+            .push_str(
+                "test.wit",
+                "package test:test;\n\
+                 package wasi:cli@0.3.0-rc-2026-03-15 { interface environment { f: func(); } }\n\
+                 world w { import wasi:cli/environment@0.3.0-rc-2026-03-15; }",
+            )
+            .unwrap();
+        let world = resolve.select_world(&[package], Some("w")).unwrap();
+        let error = check_adapter(
+            &adapter(&["wasi:cli/environment@0.3.0-rc-2025-09-16#f"]),
+            "adapter",
+            &resolve,
+            world,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("incompatible WASI"), "{error}");
+        check_adapter(
+            &adapter(&["wasi:cli/environment@0.3.0-rc-2026-03-15#f"]),
+            "adapter",
+            &resolve,
+            world,
+        )
+        .unwrap();
+    }
 
-    Ok(bin)
+    #[test]
+    fn test_command_adapter() {
+        // A program with nothing but an empty `main`, as `--command` builds.
+        let bytes = testing::component(&testing::module(&[], "_start", &[]), Adapter::Command);
+
+        let (resolve, world) = crate::targets::decode(&bytes).unwrap();
+        assert!(
+            resolve.worlds[world]
+                .exports
+                .keys()
+                .any(|key| resolve.name_world_key(key).starts_with("wasi:cli/run@"))
+        );
+    }
 }
@@ -0,0 +1,403 @@
+use crate::{
+    cmd_compose::compose_plugs,
+    stubs::{DATA_OFFSET, provider, provider_module},
+    targets::decode,
+};
+use anyhow::{Context, Result, anyhow, bail};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use wasm_encoder::Function;
+use wit_parser::{Resolve, WorldId, WorldKey};
+
+const ENVIRONMENT: &str = "wasi:cli/environment@";
+const FILESYSTEM: &str = "wasi:filesystem/";
+
+/// Environment variables, arguments and files to bake into a component in
+/// place of those of the host.
+#[derive(Default)]
+pub struct Virtualization {
+    /// The environment variables to present instead of the host's, if any.
+    pub env: Option<Vec<(String, String)>>,
+
+    /// The arguments to present instead of the host's, when the environment
+    /// is virtualized.
+    pub args: Vec<String>,
+
+    /// A directory whose contents to present, read-only, as the only
+    /// preopened directory, `/`, instead of the host's, if any.
+    pub dir: Option<PathBuf>,
+}
+
+impl Virtualization {
+    pub fn is_empty(&self) -> bool {
+        self.env.is_none() && self.dir.is_none()
+    }
+}
+
+/// Compose the component in `component` with components which implement
+/// `wasi:cli/environment` and/or `wasi:filesystem` using the data in `virt`,
+/// so that it no longer imports them, returning the imports satisfied and by
+/// which.
+///
+/// The environment is implemented by a component generated here, while the
+/// filesystem is implemented by one generated by `wasi-virt`, which must be
+/// installed.
+pub fn virtualize(component: &Path, virt: &Virtualization) -> Result<BTreeMap<String, String>> {
+    let mut virtualized = BTreeMap::new();
+
+    // The environment goes first, so that the filesystem component, which
+    // also exports an environment, does not satisfy it instead.
+    if let Some(env) = &virt.env {
+        let (resolve, world) = decode(&fs::read(component)?)?;
+        let interfaces = imports(&resolve, world, ENVIRONMENT);
+        if !interfaces.is_empty() {
+            let plug = provider(&environment(resolve, &interfaces, env, &virt.args)?)?;
+            virtualized.extend(compose_plugs(
+                component,
+                &[("the virtual environment".to_string(), plug)],
+                component,
+            )?);
+        }
+    }
+
+    if let Some(dir) = &virt.dir {
+        check_tree(dir)?;
+        let plug = filesystem(
+            dir,
+            &component.with_extension("virt.wasm"),
+            virt.env.is_none(),
+        )?;
+        virtualized.extend(compose_plugs(
+            component,
+            &[("the virtual filesystem".to_string(), plug)],
+            component,
+        )?);
+    }
+
+    let (resolve, world) = decode(&fs::read(component)?)?;
+    let remaining = [
+        (virt.env.is_some(), ENVIRONMENT),
+        (virt.dir.is_some(), FILESYSTEM),
+    ]
+    .into_iter()
+    .filter(|&(virtualized, _)| virtualized)
+    .flat_map(|(_, prefix)| imports(&resolve, world, prefix))
+    .collect::<Vec<_>>();
+    if !remaining.is_empty() {
+        bail!(
+            "the component still imports {}; check that `wasi-virt` targets the same WASI \
+             version as the adapter",
+            remaining
+                .iter()
+                .map(|import| format!("`{import}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    Ok(virtualized)
+}
+
+/// The names of the interfaces imported by `world` which start with `prefix`.
+fn imports(resolve: &Resolve, world: WorldId, prefix: &str) -> Vec<String> {
+    resolve.worlds[world]
+        .imports
+        .keys()
+        .filter_map(|key| match key {
+            WorldKey::Interface(id) => resolve.id_of(*id),
+            WorldKey::Name(_) => None,
+        })
+        .filter(|name| name.starts_with(prefix))
+        .collect()
+}
+
+/// The contents of a provider's memory, from [`DATA_OFFSET`] on.
+#[derive(Default)]
+struct Data(Vec<u8>);
+
+impl Data {
+    /// Append `bytes`, aligned to 4, returning their address.
+    fn push(&mut self, bytes: &[u8]) -> u32 {
+        self.0.resize(self.0.len().next_multiple_of(4), 0);
+        let address = DATA_OFFSET + self.0.len() as u32;
+        self.0.extend_from_slice(bytes);
+        address
+    }
+
+    /// Append a string, returning its address and length as they are laid out
+    /// in memory.
+    fn string(&mut self, string: &str) -> [u32; 2] {
+        [self.push(string.as_bytes()), string.len() as u32]
+    }
+
+    /// Append a list of records made of `u32`s, returning the address of the
+    /// list and its length, itself laid out as a record.
+    fn list<const N: usize>(&mut self, records: &[[u32; N]]) -> u32 {
+        let bytes = records
+            .iter()
+            .flatten()
+            .flat_map(|field| field.to_le_bytes())
+            .collect::<Vec<_>>();
+        let address = self.push(&bytes);
+        let list = [address, records.len() as u32]
+            .iter()
+            .flat_map(|field| field.to_le_bytes())
+            .collect::<Vec<_>>();
+        self.push(&list)
+    }
+}
+
+/// A [`provider_module`] which implements each of `interfaces`, versions of
+/// `wasi:cli/environment` as defined in `resolve`, presenting `env` and
+/// `args`, and no initial working directory.
+fn environment(
+    resolve: Resolve,
+    interfaces: &[String],
+    env: &[(String, String)],
+    args: &[String],
+) -> Result<Vec<u8>> {
+    let mut data = Data::default();
+    let env = env
+        .iter()
+        .map(|(key, value)| {
+            let [key, key_len] = data.string(key);
+            let [value, value_len] = data.string(value);
+            [key, key_len, value, value_len]
+        })
+        .collect::<Vec<_>>();
+    let env = data.list(&env);
+    let args = args.iter().map(|arg| data.string(arg)).collect::<Vec<_>>();
+    let args = data.list(&args);
+
+    provider_module(resolve, interfaces, &data.0, |_, function| {
+        // Each function returns a pointer to its result; zeroed memory
+        // represents `none`.
+        let result = match function.name.as_str() {
+            "get-environment" => env,
+            "get-arguments" => args,
+            "initial-cwd" => 0,
+            _ => return None,
+        };
+        let mut body = Function::new([]);
+        body.instructions().i32_const(result as i32).end();
+        Some(body)
+    })
+}
+
+/// Reject anything in the tree rooted at `dir` which cannot be virtualized.
+///
+/// Symbolic links are not followed, since one pointing at an ancestor would
+/// otherwise be read forever.
+fn check_tree(dir: &Path) -> Result<()> {
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let children = fs::read_dir(&dir)
+            .and_then(|children| {
+                children
+                    .map(|child| Ok(child?.path()))
+                    .collect::<std::io::Result<Vec<_>>>()
+            })
+            .with_context(|| format!("failed to read `{}`", dir.display()))?;
+        for path in children {
+            let file_type = fs::symlink_metadata(&path)
+                .with_context(|| format!("failed to read `{}`", path.display()))?
+                .file_type();
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else if file_type.is_symlink() {
+                bail!(
+                    "`{}` is a symbolic link, which cannot be virtualized; replace it with a \
+                     copy of its target",
+                    path.display()
+                );
+            } else if !file_type.is_file() {
+                bail!(
+                    "`{}` is not a regular file or directory, so it cannot be virtualized",
+                    path.display()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run `wasi-virt`, writing to the temporary file `output`, to generate a
+/// component which presents the contents of `dir`, read-only, as the only
+/// preopened directory, `/`, and passes through everything else, including
+/// the environment if `allow_env`.
+fn filesystem(dir: &Path, output: &Path, allow_env: bool) -> Result<Vec<u8>> {
+    let wasi_virt = which::which("wasi-virt").map_err(|_| {
+        anyhow!(
+            "`wasi-virt` not found in PATH, which `--virtual-dir` requires; install it with \
+             `cargo install --git https://github.com/bytecodealliance/WASI-Virt`"
+        )
+    })?;
+    let dir = dir
+        .canonicalize()
+        .with_context(|| format!("failed to read `{}`", dir.display()))?;
+
+    let mut command = Command::new(&wasi_virt);
+    command.args([
+        "--allow-clocks",
+        "--allow-exit",
+        "--allow-http",
+        "--allow-random",
+        "--allow-sockets",
+        "--allow-stdio",
+    ]);
+    if allow_env {
+        command.arg("--allow-env");
+    }
+    let status = command
+        .arg("--mount")
+        .arg(format!("/={}", dir.display()))
+        .arg("-o")
+        .arg(output)
+        .status()
+        .with_context(|| format!("failed to run `{}`", wasi_virt.display()))?;
+    let plug = fs::read(output);
+    _ = fs::remove_file(output);
+    if !status.success() {
+        bail!("`wasi-virt` failed: {status}");
+    }
+    plug.context("`wasi-virt` wrote no component")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{command::Adapter, testing};
+    use wasm_encoder::ValType;
+
+    const PREVIEW1: &str = "wasi_snapshot_preview1";
+
+    /// A command component which reads its environment and arguments.
+    fn component() -> Vec<u8> {
+        use ValType::I32;
+        let module = testing::module(
+            &[
+                (PREVIEW1, "environ_get", &[I32; 2], &[I32]),
+                (PREVIEW1, "args_get", &[I32; 2], &[I32]),
+                (PREVIEW1, "fd_write", &[I32; 4], &[I32]),
+            ],
+            "_start",
+            &[0, 1, 2],
+        );
+        testing::component(&module, Adapter::Command)
+    }
+
+    #[test]
+    fn test_virtualize_environment() {
+        let path = testing::temp_path("main.wasm");
+        fs::write(&path, component()).unwrap();
+        let before = decode(&fs::read(&path).unwrap()).unwrap();
+        assert!(!imports(&before.0, before.1, ENVIRONMENT).is_empty());
+
+        let virtualized = virtualize(
+            &path,
+            &Virtualization {
+                env: Some(vec![("KEY".into(), "value".into())]),
+                args: vec!["main.wasm".into()],
+                dir: None,
+            },
+        );
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let virtualized = virtualized.unwrap();
+        assert!(
+            virtualized.keys().all(|name| name.starts_with(ENVIRONMENT)),
+            "{virtualized:?}"
+        );
+
+        // The final component no longer imports the environment, but still
+        // imports the rest of what the adapter needs.
+        let (resolve, world) = decode(&bytes).unwrap();
+        assert_eq!(imports(&resolve, world, ENVIRONMENT), Vec::<String>::new());
+        assert!(!imports(&resolve, world, "wasi:cli/stdout@").is_empty());
+    }
+
+    #[test]
+    fn test_environment() {
+        let (resolve, world) = decode(&component()).unwrap();
+        let interfaces = imports(&resolve, world, ENVIRONMENT);
+        let module = environment(
+            resolve,
+            &interfaces,
+            &[("A".into(), "B".into()), ("HOME".into(), "/x".into())],
+            &["main.wasm".into()],
+        )
+        .unwrap();
+
+        let engine = wasmi::Engine::default();
+        let module = wasmi::Module::new(&engine, &module[..]).unwrap();
+        let mut store = wasmi::Store::new(&engine, ());
+        let instance = wasmi::Linker::<()>::new(&engine)
+            .instantiate(&mut store, &module)
+            .unwrap()
+            .start(&mut store)
+            .unwrap();
+        let memory = instance.get_memory(&store, "memory").unwrap();
+        let mut call = |name: &str| {
+            let function = instance
+                .get_func(&store, &format!("{}#{name}", interfaces[0]))
+                .unwrap();
+            let mut results = [wasmi::Val::I32(0)];
+            function.call(&mut store, &[], &mut results).unwrap();
+            results[0].i32().unwrap() as usize
+        };
+        let environment = call("get-environment");
+        let arguments = call("get-arguments");
+
+        let data = memory.data(&store);
+        let u32 = |address: usize| {
+            u32::from_le_bytes(data[address..address + 4].try_into().unwrap()) as usize
+        };
+        let string = |address: usize| {
+            String::from_utf8(data[u32(address)..][..u32(address + 4)].to_vec()).unwrap()
+        };
+        let list = |address: usize, size: usize| {
+            (0..u32(address + 4))
+                .map(|index| u32(address) + index * size)
+                .collect::<Vec<_>>()
+        };
+
+        let env = list(environment, 16)
+            .into_iter()
+            .map(|record| (string(record), string(record + 8)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            env,
+            [
+                ("A".to_string(), "B".to_string()),
+                ("HOME".to_string(), "/x".to_string())
+            ]
+        );
+        let args = list(arguments, 8)
+            .into_iter()
+            .map(string)
+            .collect::<Vec<_>>();
+        assert_eq!(args, ["main.wasm"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_tree_symlink() {
+        let dir = testing::temp_path("tree");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/a.txt"), "a").unwrap();
+        check_tree(&dir).unwrap();
+
+        // A link to an ancestor is rejected rather than followed forever.
+        std::os::unix::fs::symlink(&dir, dir.join("sub/loop")).unwrap();
+        let Err(error) = check_tree(&dir) else {
+            panic!("expected the symbolic link to be rejected");
+        };
+        assert!(error.to_string().contains("symbolic link"), "{error}");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -214,6 +214,56 @@ mod tests {
             Ok(())
         }
 
+        fn build_command(&self, args: &[&str]) -> Result<()> {
+            // Build a `wasi:cli/command` component from a plain Go program
+            let mut build_cmd = Command::new(COMPONENTIZE_GO_PATH.as_path());
+            build_cmd
+                .arg("build")
+                .arg("--command")
+                .args(args)
+                .args(["-o", &self.wasm_path]);
+
+            // Run `go build` in the same directory as the go.mod file.
+            build_cmd.current_dir(&self.path);
+
+            let build_output = build_cmd.output().expect(&format!(
+                "failed to execute componentize-go for \"{}\"",
+                self.path.display()
+            ));
+
+            if !build_output.status.success() {
+                return Err(anyhow!(
+                    "failed to build application \"{}\": {}",
+                    self.path.display(),
+                    String::from_utf8_lossy(&build_output.stderr)
+                ));
+            }
+
+            Ok(())
+        }
+
+        /// The names of the interfaces exported by the built component.
+        fn component_exports(&self) -> Result<Vec<String>> {
+            let bytes = std::fs::read(&self.wasm_path)?;
+            let (resolve, world) = componentize_go::targets::decode(&bytes)?;
+            Ok(resolve.worlds[world]
+                .exports
+                .keys()
+                .map(|key| resolve.name_world_key(key))
+                .collect())
+        }
+
+        /// The names of the interfaces imported by the built component.
+        fn component_imports(&self) -> Result<Vec<String>> {
+            let bytes = std::fs::read(&self.wasm_path)?;
+            let (resolve, world) = componentize_go::targets::decode(&bytes)?;
+            Ok(resolve.worlds[world]
+                .imports
+                .keys()
+                .map(|key| resolve.name_world_key(key))
+                .collect())
+        }
+
         fn build_component(&self) -> Result<()> {
             self.generate_bindings()?;
 
@@ -340,6 +390,43 @@ mod tests {
         app.run_module().expect("failed to run app module");
     }
 
+    #[test]
+    fn example_wasip1_command() {
+        let app = App::new(Path::new("../examples/wasip1"), &[], &[], None, false);
+        app.build_command(&[]).expect("failed to build app command");
+        let exports = app
+            .component_exports()
+            .expect("failed to decode app command");
+        assert!(
+            exports
+                .iter()
+                .any(|export| export.starts_with("wasi:cli/run@")),
+            "expected an export of `wasi:cli/run`, found {exports:?}"
+        );
+        app.run_module().expect("failed to run app command");
+    }
+
+    #[test]
+    fn example_wasip1_command_stub_unused_imports() {
+        let app = App::new(Path::new("../examples/wasip1"), &[], &[], None, false);
+        app.build_command(&[]).expect("failed to build app command");
+        let imports = app
+            .component_imports()
+            .expect("failed to decode app command");
+
+        app.build_command(&["--stub-unused-imports"])
+            .expect("failed to build app command with stubs");
+        let stubbed = app
+            .component_imports()
+            .expect("failed to decode app command with stubs");
+        assert!(
+            stubbed.iter().all(|import| imports.contains(import)),
+            "expected a subset of {imports:?}, found {stubbed:?}"
+        );
+        app.run_module()
+            .expect("failed to run app command with stubs");
+    }
+
     #[tokio::test]
     async fn example_wasip2() {
         let unit_tests = vec![